@@ -0,0 +1,192 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use crate::ast::{Fragment, Section};
+use crate::error::{PResult, ParseError};
+use crate::Span;
+
+type Document = crate::ast::Document<Span>;
+type Query = crate::ast::Query<Span>;
+type Type = crate::ast::Type<Span>;
+
+/// Return the type of a single row of the result.
+fn row_type(result_type: &Type) -> &Type {
+    match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => row_type(inner),
+        other => other,
+    }
+}
+
+fn check_query_arity(query: &Query) -> PResult<()> {
+    let result_type = &query.annotation.result_type;
+    let note = result_type
+        .span()
+        .map(|span| ("Result type declared here.", span));
+
+    // The spans of the quoted typed columns, e.g. `"id: i64"`.
+    let columns: Vec<Span> = query
+        .fragments
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::TypedIdent(span, _) => Some(*span),
+            _ => None,
+        })
+        .collect();
+
+    // Columns without a type cannot be counted, so when a query has no typed
+    // columns at all, we read the columns by position and trust the result
+    // type. When there are typed columns, they must match the result type.
+    let (expected_len, message_more, message_fewer) = match row_type(result_type) {
+        Type::Unit => return Ok(()),
+        // A scalar has one element, and we only get here with at least one
+        // column, so there cannot be fewer columns than expected.
+        Type::Simple(..) => (
+            1,
+            "Expected a single typed column for a scalar result type.",
+            "",
+        ),
+        Type::Option(..) | Type::Iterator(..) => {
+            unreachable!("The row type does not contain options or iterators.")
+        }
+        Type::Tuple(elements) => (
+            elements.len(),
+            "Query has more typed columns than the result tuple has elements.",
+            "Query has fewer typed columns than the result tuple has elements.",
+        ),
+        Type::Struct(name, _fields) => {
+            if columns.is_empty() {
+                let err = ParseError {
+                    span: *name,
+                    message:
+                        "Expected typed columns for a struct result, e.g. 'id as \"id: i64\"'.",
+                    note: None,
+                };
+                return Err(err);
+            }
+            return Ok(());
+        }
+    };
+
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    if columns.len() > expected_len {
+        let err = ParseError {
+            span: columns[expected_len],
+            message: message_more,
+            note,
+        };
+        return Err(err);
+    }
+
+    if columns.len() < expected_len {
+        let err = ParseError {
+            span: columns[columns.len() - 1],
+            message: message_fewer,
+            note,
+        };
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Check that the typed columns of every query match its result type.
+///
+/// A scalar result must have a single column, and a tuple result must have as
+/// many columns as the tuple has elements. This runs after struct resolution,
+/// so struct results are consistent by construction, but they do need columns.
+pub fn check_arity(doc: &Document) -> PResult<()> {
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            check_query_arity(query)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_arity;
+    use crate::analysis::structs::resolve_structs;
+    use crate::error::ParseError;
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+
+    fn check(input: &str) -> Result<(), ParseError> {
+        let tokens = Lexer::new(input).run().expect("Failed to lex the input.");
+        let mut parser = Parser::new(input, &tokens);
+        let mut doc = parser.parse_document().expect("Failed to parse the input.");
+        resolve_structs(input, &mut doc);
+        check_arity(&doc)
+    }
+
+    #[test]
+    fn check_arity_accepts_example_file_users() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        assert!(check(&input).is_ok());
+    }
+
+    #[test]
+    fn check_arity_accepts_matching_columns() {
+        let input = r#"
+        -- @query get_pair() -> Option<(i64, String)>
+        select id as "id: i64", name as "name: String" from users;
+
+        -- @query get_name() -> Iterator<String>
+        select name as "name: String" from users;
+
+        -- @query get_untyped() -> (i64, String)
+        select id, name from users;
+        "#;
+        assert!(check(input).is_ok());
+    }
+
+    #[test]
+    fn check_arity_reports_multiple_columns_for_scalar() {
+        let input = r#"
+        -- @query get_name() -> Option<String>
+        select name as "name: String", email as "email: String" from users;
+        "#;
+        let err = check(input).unwrap_err();
+        assert_eq!(err.span.resolve(input), "\"email: String\"");
+        let (_, note_span) = err.note.unwrap();
+        assert_eq!(note_span.resolve(input), "String");
+    }
+
+    #[test]
+    fn check_arity_reports_tuple_length_mismatch() {
+        let input = r#"
+        -- @query get_pair() -> (i64, String)
+        select id as "id: i64" from users;
+        "#;
+        let err = check(input).unwrap_err();
+        assert!(err.message.contains("fewer"));
+        assert_eq!(err.span.resolve(input), "\"id: i64\"");
+        let (_, note_span) = err.note.unwrap();
+        assert_eq!(note_span.resolve(input), "i64, String");
+
+        let input = r#"
+        -- @query get_pair() -> (i64, String)
+        select id as "id: i64", name as "name: String", 1 as "one: i64" from users;
+        "#;
+        let err = check(input).unwrap_err();
+        assert!(err.message.contains("more"));
+        assert_eq!(err.span.resolve(input), "\"one: i64\"");
+    }
+
+    #[test]
+    fn check_arity_reports_struct_without_columns() {
+        let input = "
+        -- @query get_user() -> User
+        select * from users;
+        ";
+        let err = check(input).unwrap_err();
+        assert_eq!(err.span.resolve(input), "User");
+    }
+}
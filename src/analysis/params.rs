@@ -0,0 +1,222 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use crate::ast::{Fragment, ParamKind, Section};
+use crate::error::{PResult, ParseError, Warning};
+use crate::target::param_name;
+use crate::Span;
+
+type Document = crate::ast::Document<Span>;
+type Query = crate::ast::Query<Span>;
+
+/// Return the span from the start of the first fragment to the end of the last.
+fn query_span(query: &Query) -> Option<Span> {
+    let span = |fragment: &Fragment<Span>| match fragment {
+        Fragment::Verbatim(span) | Fragment::TypedIdent(span, _) | Fragment::Param(span, _) => {
+            *span
+        }
+    };
+    let first = span(query.fragments.first()?);
+    let last = span(query.fragments.last()?);
+    Some(Span {
+        start: first.start,
+        end: last.end,
+    })
+}
+
+fn check_query_params(input: &str, query: &Query, warnings: &mut Vec<Warning>) -> PResult<()> {
+    let parameters = &query.annotation.parameters;
+
+    for (i, param) in parameters.iter().enumerate() {
+        let name = param.ident.resolve(input);
+        if let Some(first) = parameters[..i]
+            .iter()
+            .find(|p| p.ident.resolve(input) == name)
+        {
+            let err = ParseError {
+                span: param.ident,
+                message: "Parameter is declared more than once.",
+                note: Some(("First declared here.", first.ident)),
+            };
+            return Err(err);
+        }
+    }
+
+    let resolved = query.resolve(input);
+    let mut used = vec![false; parameters.len()];
+    let mut first_param: Option<(Span, ParamKind)> = None;
+
+    for fragment in &query.fragments {
+        let (span, kind) = match fragment {
+            Fragment::Param(span, kind) => (*span, *kind),
+            _ => continue,
+        };
+
+        // A positional parameter after a named one, or vice versa, is almost
+        // certainly a mistake, so we do not try to number them together.
+        match first_param {
+            None => first_param = Some((span, kind)),
+            Some((first_span, first_kind))
+                if first_kind.position().is_some() != kind.position().is_some() =>
+            {
+                let err = ParseError {
+                    span,
+                    message: "Query mixes named and positional parameters.",
+                    note: Some(("First parameter is here.", first_span)),
+                };
+                return Err(err);
+            }
+            Some(..) => {}
+        }
+
+        // Declared names are unique, so the name identifies the parameter.
+        let name = param_name(&resolved, span.resolve(input), kind);
+        match parameters
+            .iter()
+            .position(|p| p.ident.resolve(input) == name)
+        {
+            Some(i) => used[i] = true,
+            None => {
+                let err = ParseError {
+                    span,
+                    message: "Parameter is not declared in the annotation.",
+                    note: Some(("Parameters are declared here.", query.annotation.name)),
+                };
+                return Err(err);
+            }
+        }
+    }
+
+    for (param, is_used) in parameters.iter().zip(used) {
+        if !is_used {
+            warnings.push(Warning {
+                span: param.ident,
+                message: "Parameter is declared, but the query does not use it.",
+                note: query_span(query).map(|span| ("The query is here.", span)),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the declared parameters of every query against their uses.
+///
+/// Named parameters refer to declared parameters by name, positional
+/// parameters by position. Using a parameter that is not declared in the
+/// annotation is an error, and so is mixing named and positional parameters.
+/// Declaring a parameter that the query does not use is not necessarily
+/// wrong, so that only produces a warning.
+pub fn check_params(input: &str, doc: &Document) -> PResult<Vec<Warning>> {
+    let mut warnings = Vec::new();
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            check_query_params(input, query, &mut warnings)?;
+        }
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_params;
+    use crate::error::{PResult, Warning};
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+
+    fn check(input: &str) -> PResult<Vec<Warning>> {
+        let tokens = Lexer::new(input).run().expect("Failed to lex the input.");
+        let mut parser = Parser::new(input, &tokens);
+        let doc = parser.parse_document().expect("Failed to parse the input.");
+        check_params(input, &doc)
+    }
+
+    #[test]
+    fn check_params_accepts_example_file_users() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let warnings = check(&input).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn check_params_reports_undeclared_parameter() {
+        let input = "
+        -- @query get_user(id: i64) -> i64
+        select id from users where id = :id or name = :name;
+        ";
+        let err = check(input).unwrap_err();
+        assert_eq!(err.span.resolve(input), ":name");
+        let (_, note_span) = err.note.unwrap();
+        assert_eq!(note_span.resolve(input), "get_user");
+    }
+
+    #[test]
+    fn check_params_reports_undeclared_parameter_inside_parens() {
+        let input = "
+        -- @query get_user() -> i64
+        select id from users where id in (select :id);
+        ";
+        let err = check(input).unwrap_err();
+        assert_eq!(err.span.resolve(input), ":id");
+    }
+
+    #[test]
+    fn check_params_reports_duplicate_parameter() {
+        let input = "
+        -- @query get_user(id: i64, id: i64) -> i64
+        select id from users where id = :id;
+        ";
+        let err = check(input).unwrap_err();
+        assert!(err.message.contains("more than once"));
+        assert_eq!(err.span.start, input.rfind("id: i64)").unwrap());
+    }
+
+    #[test]
+    fn check_params_binds_positional_parameters_by_position() {
+        let input = "
+        -- @query get_user(id: i64, name: &str) -> i64
+        select id from users where id = ?1 and name = ?2 and id <> $1;
+        ";
+        let warnings = check(input).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn check_params_reports_mixed_parameter_styles() {
+        let input = "
+        -- @query get_user(id: i64, name: &str) -> i64
+        select id from users where id = :id and name = ?2;
+        ";
+        let err = check(input).unwrap_err();
+        assert!(err.message.contains("mixes named and positional"));
+        assert_eq!(err.span.resolve(input), "?2");
+    }
+
+    #[test]
+    fn check_params_warns_about_unused_positional_parameter() {
+        let input = "
+        -- @query get_user(id: i64, name: &str) -> i64
+        select id from users where id = ?;
+        ";
+        let warnings = check(input).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].span.resolve(input), "name");
+    }
+
+    #[test]
+    fn check_params_warns_about_unused_parameter() {
+        let input = "
+        -- @query get_user(id: i64, name: &str) -> i64
+        select id from users where id = :id;
+        ";
+        let warnings = check(input).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].span.resolve(input), "name");
+        let (_, query) = warnings[0].note.unwrap();
+        assert_eq!(query.resolve(input), "select id from users where id = :id;");
+    }
+}
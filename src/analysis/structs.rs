@@ -0,0 +1,338 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use crate::ast::{Fragment, Section};
+use crate::error::{PResult, ParseError};
+use crate::Span;
+
+type Document = crate::ast::Document<Span>;
+type Query = crate::ast::Query<Span>;
+type Type = crate::ast::Type<Span>;
+type TypedIdent = crate::ast::TypedIdent<Span>;
+
+/// Capitalized type names that are not structs, even though they look like one.
+const BUILTIN_TYPES: &[&str] = &["String"];
+
+/// Return whether a simple result type refers to a struct.
+fn is_struct_name(name: &str) -> bool {
+    let is_capitalized = name
+        .bytes()
+        .next()
+        .map_or(false, |ch| ch.is_ascii_uppercase());
+    is_capitalized && !BUILTIN_TYPES.contains(&name)
+}
+
+/// Return the typed identifiers that occur in the query, in order.
+fn typed_columns(query: &Query) -> Vec<TypedIdent> {
+    query
+        .fragments
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::TypedIdent(_, typed_ident) => Some(typed_ident.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Return the simple type inside options and iterators, if there is one.
+fn base_type_name(type_: &Type) -> Option<Span> {
+    match type_ {
+        Type::Option(inner) | Type::Iterator(inner) => base_type_name(inner),
+        Type::Simple(name) => Some(*name),
+        _ => None,
+    }
+}
+
+/// Return whether the query selects a single column of the result type itself.
+///
+/// In that case a capitalized result type is a scalar, e.g. `-> Uuid` with
+/// `select id as "id: Uuid"`, not a struct with one field.
+fn is_scalar_column(input: &str, result_type: &Type, fields: &[TypedIdent]) -> bool {
+    let resolve = |type_: &Type| base_type_name(type_).map(|name| name.resolve(input));
+    match fields {
+        [field] => resolve(&field.type_).is_some() && resolve(&field.type_) == resolve(result_type),
+        _ => false,
+    }
+}
+
+/// Replace the simple type inside the result type with a struct, if it is one.
+///
+/// Only the type itself, or the type inside an option or iterator, can be a
+/// struct. A struct cannot be part of a tuple, because the columns of the
+/// struct would be ambiguous.
+fn resolve_result_type(input: &str, result_type: &mut Type, fields: Vec<TypedIdent>) {
+    match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => resolve_result_type(input, inner, fields),
+        Type::Simple(name) if is_struct_name(name.resolve(input)) => {
+            *result_type = Type::Struct(*name, fields);
+        }
+        _ => {}
+    }
+}
+
+/// Turn bare capitalized result types into struct types.
+///
+/// The annotation parser produces `Type::Simple("User")` for `-> User`. This
+/// pass replaces that with a `Type::Struct` whose fields are the typed columns
+/// of the query, e.g. `select id as "id: i64"`. When the only typed column
+/// has the result type itself, the type is a scalar, not a struct.
+pub fn resolve_structs(input: &str, doc: &mut Document) {
+    for section in doc.sections.iter_mut() {
+        if let Section::Query(query) = section {
+            let fields = typed_columns(query);
+            let result_type = &mut query.annotation.result_type;
+            if !is_scalar_column(input, result_type, &fields) {
+                resolve_result_type(input, result_type, fields);
+            }
+        }
+    }
+}
+
+/// Return the name and fields of the struct inside a result type, if there is one.
+fn get_struct(result_type: &Type) -> Option<(Span, &[TypedIdent])> {
+    match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => get_struct(inner),
+        Type::Struct(name, fields) => Some((*name, fields)),
+        _ => None,
+    }
+}
+
+/// Check that a struct definition is consistent with its first definition.
+fn check_struct_matches(
+    input: &str,
+    first: (Span, &[TypedIdent]),
+    other: (Span, &[TypedIdent]),
+) -> PResult<()> {
+    let (first_name, first_fields) = first;
+    let (other_name, other_fields) = other;
+
+    for (first_field, other_field) in first_fields.iter().zip(other_fields) {
+        let message = if first_field.ident.resolve(input) != other_field.ident.resolve(input) {
+            "Field name differs from an earlier definition of this struct."
+        } else if first_field.type_.resolve(input) != other_field.type_.resolve(input) {
+            "Field type differs from an earlier definition of this struct."
+        } else {
+            continue;
+        };
+        let err = ParseError {
+            span: other_field.ident,
+            message,
+            note: Some(("First defined here.", first_field.ident)),
+        };
+        return Err(err);
+    }
+
+    if other_fields.len() > first_fields.len() {
+        let err = ParseError {
+            span: other_fields[first_fields.len()].ident,
+            message: "Struct has more fields than an earlier definition of this struct.",
+            note: Some(("First defined here.", first_name)),
+        };
+        return Err(err);
+    }
+
+    if other_fields.len() < first_fields.len() {
+        let err = ParseError {
+            span: other_name,
+            message: "Struct has fewer fields than an earlier definition of this struct.",
+            note: Some((
+                "First definition has this field.",
+                first_fields[other_fields.len()].ident,
+            )),
+        };
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Check that queries which return the same struct agree on its fields.
+///
+/// Every struct gets generated once, so the typed columns of all queries that
+/// return it must have the same names and types, in the same order.
+pub fn check_structs(input: &str, doc: &Document) -> PResult<()> {
+    let mut definitions: Vec<(Span, &[TypedIdent])> = Vec::new();
+
+    for section in &doc.sections {
+        let query = match section {
+            Section::Query(query) => query,
+            Section::Verbatim(..) => continue,
+        };
+        let (name, fields) = match get_struct(&query.annotation.result_type) {
+            Some(def) => def,
+            None => continue,
+        };
+        let first = definitions
+            .iter()
+            .find(|(first_name, _)| first_name.resolve(input) == name.resolve(input));
+        match first {
+            Some(first) => check_struct_matches(input, *first, (name, fields))?,
+            None => definitions.push((name, fields)),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_structs, resolve_structs};
+    use crate::ast::{Section, Type, TypedIdent};
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+
+    fn with_result_types<F: FnOnce(Vec<Type<&str>>)>(input: &str, f: F) {
+        let tokens = Lexer::new(input).run().expect("Failed to lex the input.");
+        let mut parser = Parser::new(input, &tokens);
+        let mut doc = parser.parse_document().expect("Failed to parse the input.");
+        resolve_structs(input, &mut doc);
+        let result_types = doc
+            .resolve(input)
+            .sections
+            .into_iter()
+            .filter_map(|section| match section {
+                Section::Query(query) => Some(query.annotation.result_type),
+                _ => None,
+            })
+            .collect();
+        f(result_types)
+    }
+
+    #[test]
+    fn resolve_structs_replaces_capitalized_types() {
+        let input = r#"
+        -- @query get_user() -> Option<User>
+        select id as "id: i64", name as "name: String" from users;
+
+        -- @query iter_users() -> Iterator<User>
+        select id as "id: i64" from users;
+        "#;
+        with_result_types(input, |result_types| {
+            let id = TypedIdent {
+                ident: "id",
+                type_: Type::Simple("i64"),
+            };
+            let name = TypedIdent {
+                ident: "name",
+                type_: Type::Simple("String"),
+            };
+            let expected = vec![
+                Type::Option(Box::new(Type::Struct("User", vec![id.clone(), name]))),
+                Type::Iterator(Box::new(Type::Struct("User", vec![id]))),
+            ];
+            assert_eq!(result_types, expected);
+        });
+    }
+
+    #[test]
+    fn resolve_structs_leaves_other_types_alone() {
+        let input = r#"
+        -- @query get_name() -> String
+        select name as "name: String" from users;
+
+        -- @query get_id() -> i64
+        select id from users;
+
+        -- @query get_pair() -> (i64, Pair)
+        select id as "id: i64", pair as "pair: Pair" from users;
+
+        -- @query get_uuid() -> Uuid
+        select uuid as "uuid: Uuid" from users;
+
+        -- @query iter_logins() -> Iterator<Option<DateTime>>
+        select login as "login: Option<DateTime>" from users;
+        "#;
+        with_result_types(input, |result_types| {
+            let expected = vec![
+                Type::Simple("String"),
+                Type::Simple("i64"),
+                Type::Tuple(vec![Type::Simple("i64"), Type::Simple("Pair")]),
+                Type::Simple("Uuid"),
+                Type::Iterator(Box::new(Type::Option(Box::new(Type::Simple("DateTime"))))),
+            ];
+            assert_eq!(result_types, expected);
+        });
+    }
+
+    fn check(input: &str) -> Result<(), (&str, &str, &str)> {
+        let tokens = Lexer::new(input).run().expect("Failed to lex the input.");
+        let mut parser = Parser::new(input, &tokens);
+        let mut doc = parser.parse_document().expect("Failed to parse the input.");
+        resolve_structs(input, &mut doc);
+        check_structs(input, &doc).map_err(|err| {
+            let (_, note_span) = err.note.expect("Struct errors should have a note.");
+            (
+                err.message,
+                err.span.resolve(input),
+                note_span.resolve(input),
+            )
+        })
+    }
+
+    #[test]
+    fn check_structs_accepts_example_file_users() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        assert_eq!(check(&input), Ok(()));
+    }
+
+    #[test]
+    fn check_structs_reports_field_name_mismatch() {
+        let input = r#"
+        -- @query get_user() -> User
+        select id as "id: i64", name as "name: String" from users;
+
+        -- @query list_users() -> Iterator<User>
+        select id as "id: i64", name as "nmae: String" from users;
+        "#;
+        let (message, span, note_span) = check(input).unwrap_err();
+        assert!(message.contains("Field name differs"));
+        assert_eq!(span, "nmae");
+        assert_eq!(note_span, "name");
+    }
+
+    #[test]
+    fn check_structs_reports_field_type_mismatch() {
+        let input = r#"
+        -- @query get_user() -> User
+        select id as "id: i64" from users;
+
+        -- @query list_users() -> Iterator<User>
+        select id as "id: Option<i64>" from users;
+        "#;
+        let (message, span, note_span) = check(input).unwrap_err();
+        assert!(message.contains("Field type differs"));
+        assert_eq!(span, "id");
+        assert_eq!(note_span, "id");
+    }
+
+    #[test]
+    fn check_structs_reports_field_count_mismatch() {
+        let input = r#"
+        -- @query get_user() -> User
+        select id as "id: i64" from users;
+
+        -- @query list_users() -> Iterator<User>
+        select id as "id: i64", name as "name: String" from users;
+        "#;
+        let (message, span, note_span) = check(input).unwrap_err();
+        assert!(message.contains("more fields"));
+        assert_eq!(span, "name");
+        assert_eq!(note_span, "User");
+
+        let input = r#"
+        -- @query get_user() -> User
+        select id as "id: i64", name as "name: String" from users;
+
+        -- @query list_users() -> Iterator<User>
+        select id as "id: i64" from users;
+        "#;
+        let (message, span, note_span) = check(input).unwrap_err();
+        assert!(message.contains("fewer fields"));
+        assert_eq!(span, "User");
+        assert_eq!(note_span, "name");
+    }
+}
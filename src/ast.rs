@@ -8,7 +8,7 @@
 use crate::Span;
 
 /// Types of parameters and results.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Type<TSpan> {
     /// The unit type, for queries that do not return anything.
     Unit,
@@ -33,6 +33,31 @@ pub enum Type<TSpan> {
 }
 
 impl Type<Span> {
+    /// Return the span that covers the names in this type, if it has any.
+    ///
+    /// The span does not include surrounding syntax, e.g. for `Option<i64>`
+    /// it covers only `i64`, and the unit type and empty tuple have no span.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Type::Unit => None,
+            Type::Simple(span) => Some(*span),
+            Type::Iterator(t) => t.span(),
+            Type::Option(t) => t.span(),
+            Type::Tuple(ts) => {
+                ts.iter()
+                    .filter_map(|t| t.span())
+                    .fold(None, |acc: Option<Span>, span| match acc {
+                        None => Some(span),
+                        Some(acc) => Some(Span {
+                            start: acc.start,
+                            end: span.end,
+                        }),
+                    })
+            }
+            Type::Struct(name, _fields) => Some(*name),
+        }
+    }
+
     pub fn resolve<'a>(&self, input: &'a str) -> Type<&'a str> {
         match self {
             Type::Unit => Type::Unit,
@@ -49,7 +74,7 @@ impl Type<Span> {
 }
 
 /// An identifier and a type, e.g. `name: &str`.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TypedIdent<TSpan> {
     pub ident: TSpan,
     pub type_: Type<TSpan>,
@@ -82,6 +107,46 @@ impl Annotation<Span> {
     }
 }
 
+/// The syntax of a query parameter.
+///
+/// Named parameters bind to the declared parameter with the same name,
+/// positional parameters bind to the declared parameter at their 1-based
+/// position. Positions are not checked by the parser, see `check_params`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParamKind {
+    /// `:name`.
+    Colon,
+
+    /// `@name`.
+    At,
+
+    /// A bare `?`.
+    ///
+    /// As in SQLite, its position is one more than the largest position of
+    /// the positional parameters before it.
+    Question(usize),
+
+    /// `?NNN`.
+    QuestionNumbered(usize),
+
+    /// `$NNN`.
+    Dollar(usize),
+}
+
+impl ParamKind {
+    /// Return the 1-based position that a positional parameter binds to.
+    ///
+    /// Returns `None` for named parameters.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            ParamKind::Colon | ParamKind::At => None,
+            ParamKind::Question(n) | ParamKind::QuestionNumbered(n) | ParamKind::Dollar(n) => {
+                Some(*n)
+            }
+        }
+    }
+}
+
 /// A part of a query.
 ///
 /// We break down queries in consecutive spans of three kinds:
@@ -90,12 +155,12 @@ impl Annotation<Span> {
 /// * Typed identifiers, the quoted part in a `select ... as "ident: type"`
 ///   select. These are kept separately, such that we can replace this with
 ///   just `ident` in the final query.
-/// * Parameters. These include the leading `:`.
+/// * Parameters. These include the leading `:`, `@`, `?` or `$`.
 #[derive(Debug, Eq, PartialEq)]
 pub enum Fragment<TSpan> {
     Verbatim(TSpan),
     TypedIdent(TSpan, TypedIdent<TSpan>),
-    Param(TSpan),
+    Param(TSpan, ParamKind),
 }
 
 impl Fragment<Span> {
@@ -105,7 +170,7 @@ impl Fragment<Span> {
             Fragment::TypedIdent(s, ti) => {
                 Fragment::TypedIdent(s.resolve(input), ti.resolve(input))
             }
-            Fragment::Param(s) => Fragment::Param(s.resolve(input)),
+            Fragment::Param(s, kind) => Fragment::Param(s.resolve(input), *kind),
         }
     }
 }
@@ -0,0 +1,224 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The command-line interface.
+//!
+//! The `querybinder` binary runs this with the built-in backends. Crates that
+//! add their own backends can run it with a registry that includes those.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::arity::check_arity;
+use crate::analysis::params::check_params;
+use crate::analysis::structs::{check_structs, resolve_structs};
+use crate::ast::Document;
+use crate::dialect::Dialect;
+use crate::error::{Error, Warning};
+use crate::lexer::sql::Lexer;
+use crate::parser::document::Parser;
+use crate::target::plugin::Plugin;
+use crate::target::template::Template;
+use crate::target::{Backend, Options, Registry};
+use crate::Span;
+
+#[derive(clap::Parser, Debug)]
+#[clap(version)]
+pub struct Args {
+    /// Target to generate code for, use --target=help to list supported targets.
+    #[clap(
+        value_parser,
+        long = "target",
+        short = 't',
+        value_name = "TARGET",
+        required_unless_present_any = &["plugin", "template"]
+    )]
+    pub target: Option<String>,
+
+    /// External program to generate code with, instead of a built-in target.
+    ///
+    /// The program receives the parsed queries as JSON on stdin, and its
+    /// stdout becomes the output.
+    #[clap(
+        value_parser,
+        long = "plugin",
+        value_name = "PROGRAM",
+        conflicts_with = "target"
+    )]
+    pub plugin: Option<PathBuf>,
+
+    /// Template file to render for every input file, instead of a built-in target.
+    #[clap(
+        value_parser,
+        long = "template",
+        value_name = "FILE",
+        conflicts_with_all = &["target", "plugin"]
+    )]
+    pub template: Option<PathBuf>,
+
+    /// SQL dialect of the queries.
+    ///
+    /// The dialect determines how strings and quoted identifiers are lexed,
+    /// and which database targets that support multiple databases generate
+    /// code for.
+    #[clap(arg_enum, long = "dialect", short = 'd', default_value = "sqlite")]
+    pub dialect: Dialect,
+
+    /// Map a type in annotations to a different type in the target language.
+    ///
+    /// For example, `--map-type i64=bigint`. Can be repeated. Only some
+    /// targets support this, the others reject it.
+    #[clap(long = "map-type", value_name = "FROM=TO", value_parser = parse_type_mapping)]
+    pub type_overrides: Vec<(String, String)>,
+
+    /// SQL files to process.
+    #[clap(value_parser, value_name = "FILE", required = true)]
+    pub input_files: Vec<PathBuf>,
+}
+
+impl Args {
+    /// Alternative name for `parse` to avoid `Parser` name collision.
+    pub fn get() -> Self {
+        use clap::Parser;
+        Self::parse()
+    }
+}
+
+/// Parse a `FROM=TO` argument of `--map-type`.
+fn parse_type_mapping(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_string(), to.to_string()))
+        }
+        _ => Err("Expected a mapping of the form FROM=TO, e.g. i64=bigint.".to_string()),
+    }
+}
+
+fn print_available_targets(registry: &Registry) -> io::Result<()> {
+    use std::io::Write;
+    use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+
+    writeln!(&mut stdout, "Supported targets:\n")?;
+
+    // The help pseudo-target is not a backend, but we do list it.
+    let mut entries: Vec<(&str, &str)> = registry
+        .iter()
+        .map(|backend| (backend.name(), backend.description()))
+        .collect();
+    entries.push(("help", "List all supported targets."));
+    let name_width = entries
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+
+    for (name, description) in &entries {
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
+        write!(&mut stdout, "  {:>width$}", name, width = name_width)?;
+        stdout.set_color(ColorSpec::new().set_fg(None))?;
+        writeln!(&mut stdout, "    {}", description)?;
+    }
+
+    Ok(())
+}
+
+/// The input as text, the document parsed from it, and warnings about it.
+type CheckedInput<'a> = (&'a str, Document<Span>, Vec<Warning>);
+
+/// Parse and check the input, return the parsed document and any warnings.
+fn process_input(input_bytes: &[u8], dialect: Dialect) -> Result<CheckedInput, Box<dyn Error>> {
+    let input_str = crate::str_from_utf8(input_bytes)?;
+    let tokens = Lexer::with_dialect(input_str, dialect).run()?;
+    let mut parser = Parser::new(input_str, &tokens);
+    let mut doc = parser.parse_document()?;
+    resolve_structs(input_str, &mut doc);
+    check_structs(input_str, &doc)?;
+    check_arity(&doc)?;
+    let warnings = check_params(input_str, &doc)?;
+    Ok((input_str, doc, warnings))
+}
+
+/// Read and parse the template, or exit with an error.
+fn load_template(fname: &Path) -> Template {
+    let source = std::fs::read_to_string(fname).expect("Failed to read template file.");
+    match Template::parse(fname.to_path_buf(), source.clone()) {
+        Ok(template) => template,
+        Err(err) => {
+            let err: Box<dyn Error> = err.into();
+            err.print(fname, source.as_bytes());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parse the command-line arguments and process the input files.
+///
+/// The targets that `--target` accepts are the backends in `registry`.
+pub fn run(registry: &Registry) {
+    let args = Args::get();
+
+    if args.target.as_deref() == Some("help") {
+        print_available_targets(registry).expect("Oh no, failed to print.");
+        std::process::exit(0);
+    }
+
+    let plugin = args.plugin.clone().map(Plugin::new);
+    let template = args.template.as_ref().map(|fname| load_template(fname));
+    let backend: &dyn Backend = match (&plugin, &template, &args.target) {
+        (Some(plugin), _, _) => plugin,
+        (None, Some(template), _) => template,
+        (None, None, Some(target)) => match registry.get(target) {
+            Some(backend) => backend,
+            None => {
+                eprintln!(
+                    "Unknown target '{}', use --target=help to list supported targets.",
+                    target
+                );
+                std::process::exit(1);
+            }
+        },
+        (None, None, None) => {
+            unreachable!("The CLI parser requires --target, --plugin, or --template.")
+        }
+    };
+
+    if !args.type_overrides.is_empty() && !backend.supports_type_overrides() {
+        eprintln!("The {} target does not support --map-type.", backend.name());
+        std::process::exit(1);
+    }
+
+    let options = Options {
+        dialect: args.dialect,
+        type_overrides: args.type_overrides,
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for fname in &args.input_files {
+        let input = std::fs::read(fname).expect("Failed to read input file.");
+        let (input_str, doc, warnings) = match process_input(&input, options.dialect) {
+            Ok(result) => result,
+            Err(err) => {
+                err.print(&fname, &input);
+                std::process::exit(1);
+            }
+        };
+        for warning in &warnings {
+            warning.print(&fname, &input);
+        }
+        if let Err(err) = backend.generate(input_str, &doc, &options, &mut stdout) {
+            eprintln!(
+                "Failed to generate output for {}: {}",
+                fname.to_string_lossy(),
+                err
+            );
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use clap::ValueEnum;
+
+/// The database that the queries are written for.
+///
+/// The dialect determines how the lexer treats strings and identifiers. Targets
+/// that generate code for only one database ignore the dialect, targets that
+/// do not support it report an error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Dialect {
+    /// SQLite.
+    Sqlite,
+
+    /// PostgreSQL.
+    Postgres,
+
+    /// MySQL, with backslash escapes in strings.
+    Mysql,
+}
+
+impl Dialect {
+    /// The name of the dialect, as accepted by `--dialect`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dialect::Sqlite => "sqlite",
+            Dialect::Postgres => "postgres",
+            Dialect::Mysql => "mysql",
+        }
+    }
+}
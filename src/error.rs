@@ -30,24 +30,35 @@ pub trait Error {
 }
 
 impl dyn Error {
+    /// Print the error to stderr, with the relevant part of the input highlighted.
     pub fn print(&self, fname: &Path, input: &[u8]) {
         let bold_red = "\x1b[31;1m";
-        let bold_yellow = "\x1b[33;1m";
-        let reset = "\x1b[0m";
+        print_diagnostic(self, "Error", bold_red, fname, input);
+    }
+}
 
-        let highlight = highlight_span_in_line(fname, input, self.span(), bold_red);
-        print!("{}", highlight);
-        println!("{}Error:{} {}", bold_red, reset, self.message());
+fn print_diagnostic(
+    diagnostic: &dyn Error,
+    label: &str,
+    label_ansi: &str,
+    fname: &Path,
+    input: &[u8],
+) {
+    let bold_yellow = "\x1b[33;1m";
+    let reset = "\x1b[0m";
 
-        if let Some((note, note_span)) = self.note() {
-            let highlight = highlight_span_in_line(fname, input, note_span, bold_yellow);
-            print!("\n{}", highlight);
-            println!("{}Note:{} {}", bold_yellow, reset, note);
-        }
+    let highlight = highlight_span_in_line(fname, input, diagnostic.span(), label_ansi);
+    eprint!("{}", highlight);
+    eprintln!("{}{}:{} {}", label_ansi, label, reset, diagnostic.message());
 
-        if let Some(hint) = self.hint() {
-            println!("{}Hint:{} {}", bold_yellow, reset, hint);
-        }
+    if let Some((note, note_span)) = diagnostic.note() {
+        let highlight = highlight_span_in_line(fname, input, note_span, bold_yellow);
+        eprint!("\n{}", highlight);
+        eprintln!("{}Note:{} {}", bold_yellow, reset, note);
+    }
+
+    if let Some(hint) = diagnostic.hint() {
+        eprintln!("{}Hint:{} {}", bold_yellow, reset, hint);
     }
 }
 
@@ -158,5 +169,36 @@ impl Error for ParseError {
     }
 }
 
+/// A problem that does not prevent generating code, but is likely a mistake.
+#[derive(Debug)]
+pub struct Warning {
+    pub span: Span,
+    pub message: &'static str,
+    pub note: Option<(&'static str, Span)>,
+}
+
+impl Warning {
+    /// Print the warning to stderr, with the relevant part of the input highlighted.
+    pub fn print(&self, fname: &Path, input: &[u8]) {
+        let bold_yellow = "\x1b[33;1m";
+        print_diagnostic(self, "Warning", bold_yellow, fname, input);
+    }
+}
+
+impl Error for Warning {
+    fn span(&self) -> Span {
+        self.span
+    }
+    fn message(&self) -> &str {
+        self.message
+    }
+    fn note(&self) -> Option<(&str, Span)> {
+        self.note
+    }
+    fn hint(&self) -> Option<&str> {
+        None
+    }
+}
+
 /// A parse result, either the parsed value, or a parse error.
 pub type PResult<T> = std::result::Result<T, ParseError>;
@@ -0,0 +1,279 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! JSON encoding of parsed documents, the protocol for external generators.
+//!
+//! A plugin receives a single JSON object on stdin, and writes the generated
+//! code to stdout. The object has the following shape, the full schema is in
+//! `plugin-protocol.schema.json` in the root of the repository:
+//!
+//! ```text
+//! {
+//!   "version": 2,
+//!   "options": { "dialect": "sqlite", "type_overrides": [["i64", "bigint"]] },
+//!   "document": { "sections": [Section] }
+//! }
+//! ```
+//!
+//! Every enum is an object with a `"kind"` field that names the variant. Text
+//! is resolved: it is the source text that a span refers to. A change that
+//! would break existing plugins increments `PROTOCOL_VERSION`; adding fields
+//! to objects does not.
+
+use std::io;
+
+use crate::ast::{Annotation, Document, Fragment, ParamKind, Query, Section, Type, TypedIdent};
+use crate::target::{param_name, Options};
+
+/// The version of the plugin protocol, the `"version"` field of the request.
+///
+/// Version 2 added the `style` and `position` of parameters, and changed the
+/// `name` of a parameter to the declared parameter that it binds to.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Write `s` as a JSON string literal, including quotes.
+pub fn write_string(s: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    write!(out, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            ch if (ch as u32) < 0x20 => write!(out, "\\u{:04x}", ch as u32)?,
+            ch => write!(out, "{}", ch)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+/// Write a JSON array, with `write_item` writing every element.
+fn write_array<T, F>(items: &[T], out: &mut dyn io::Write, mut write_item: F) -> io::Result<()>
+where
+    F: FnMut(&T, &mut dyn io::Write) -> io::Result<()>,
+{
+    write!(out, "[")?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write_item(item, out)?;
+    }
+    write!(out, "]")
+}
+
+pub fn write_type(type_: &Type<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    match type_ {
+        Type::Unit => write!(out, r#"{{"kind":"unit"}}"#),
+        Type::Simple(name) => {
+            write!(out, r#"{{"kind":"simple","name":"#)?;
+            write_string(name, out)?;
+            write!(out, "}}")
+        }
+        Type::Iterator(inner) => {
+            write!(out, r#"{{"kind":"iterator","inner":"#)?;
+            write_type(inner, out)?;
+            write!(out, "}}")
+        }
+        Type::Option(inner) => {
+            write!(out, r#"{{"kind":"option","inner":"#)?;
+            write_type(inner, out)?;
+            write!(out, "}}")
+        }
+        Type::Tuple(elements) => {
+            write!(out, r#"{{"kind":"tuple","elements":"#)?;
+            write_array(elements, out, |t, out| write_type(t, out))?;
+            write!(out, "}}")
+        }
+        Type::Struct(name, fields) => {
+            write!(out, r#"{{"kind":"struct","name":"#)?;
+            write_string(name, out)?;
+            write!(out, r#","fields":"#)?;
+            write_array(fields, out, |f, out| write_typed_ident(f, out))?;
+            write!(out, "}}")
+        }
+    }
+}
+
+pub fn write_typed_ident(
+    typed_ident: &TypedIdent<&str>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    write!(out, r#"{{"ident":"#)?;
+    write_string(typed_ident.ident, out)?;
+    write!(out, r#","type":"#)?;
+    write_type(&typed_ident.type_, out)?;
+    write!(out, "}}")
+}
+
+pub fn write_annotation(annotation: &Annotation<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    write!(out, r#"{{"name":"#)?;
+    write_string(annotation.name, out)?;
+    write!(out, r#","parameters":"#)?;
+    write_array(&annotation.parameters, out, |p, out| {
+        write_typed_ident(p, out)
+    })?;
+    write!(out, r#","result_type":"#)?;
+    write_type(&annotation.result_type, out)?;
+    write!(out, "}}")
+}
+
+pub fn write_fragment(
+    query: &Query<&str>,
+    fragment: &Fragment<&str>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    write!(out, "{{")?;
+    write_fragment_fields(query, fragment, out)?;
+    write!(out, "}}")
+}
+
+/// Write the fields of a fragment of `query`, without the surrounding braces.
+pub fn write_fragment_fields(
+    query: &Query<&str>,
+    fragment: &Fragment<&str>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    match fragment {
+        Fragment::Verbatim(text) => {
+            write!(out, r#""kind":"verbatim","text":"#)?;
+            write_string(text, out)
+        }
+        Fragment::TypedIdent(text, typed_ident) => {
+            write!(out, r#""kind":"typed_ident","text":"#)?;
+            write_string(text, out)?;
+            write!(out, r#","ident":"#)?;
+            write_string(typed_ident.ident, out)?;
+            write!(out, r#","type":"#)?;
+            write_type(&typed_ident.type_, out)
+        }
+        Fragment::Param(text, kind) => {
+            write!(out, r#""kind":"param","text":"#)?;
+            write_string(text, out)?;
+            // The name is the name of the declared parameter that it binds to,
+            // also for positional parameters.
+            write!(out, r#","name":"#)?;
+            write_string(param_name(query, text, *kind), out)?;
+            let style = match kind {
+                ParamKind::Colon => "colon",
+                ParamKind::At => "at",
+                ParamKind::Question(..) => "question",
+                ParamKind::QuestionNumbered(..) => "question_numbered",
+                ParamKind::Dollar(..) => "dollar",
+            };
+            write!(out, r#","style":"#)?;
+            write_string(style, out)?;
+            match kind.position() {
+                Some(n) => write!(out, r#","position":{}"#, n),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+pub fn write_query(query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    write!(out, r#"{{"docs":"#)?;
+    write_array(&query.docs, out, |d, out| write_string(d, out))?;
+    write!(out, r#","annotation":"#)?;
+    write_annotation(&query.annotation, out)?;
+    write!(out, r#","fragments":"#)?;
+    write_array(&query.fragments, out, |f, out| {
+        write_fragment(query, f, out)
+    })?;
+    write!(out, "}}")
+}
+
+pub fn write_document(doc: &Document<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    write!(out, r#"{{"sections":"#)?;
+    write_array(&doc.sections, out, |section, out| match section {
+        Section::Verbatim(text) => {
+            write!(out, r#"{{"kind":"verbatim","text":"#)?;
+            write_string(text, out)?;
+            write!(out, "}}")
+        }
+        Section::Query(query) => {
+            write!(out, r#"{{"kind":"query","query":"#)?;
+            write_query(query, out)?;
+            write!(out, "}}")
+        }
+    })?;
+    write!(out, "}}")
+}
+
+pub fn write_options(options: &Options, out: &mut dyn io::Write) -> io::Result<()> {
+    write!(out, r#"{{"dialect":"#)?;
+    write_string(options.dialect.name(), out)?;
+    write!(out, r#","type_overrides":"#)?;
+    write_array(&options.type_overrides, out, |(from, to), out| {
+        write!(out, "[")?;
+        write_string(from, out)?;
+        write!(out, ",")?;
+        write_string(to, out)?;
+        write!(out, "]")
+    })?;
+    write!(out, "}}")
+}
+
+/// Write the request that a plugin receives on stdin, followed by a newline.
+pub fn write_request(
+    doc: &Document<&str>,
+    options: &Options,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    write!(out, r#"{{"version":{},"options":"#, PROTOCOL_VERSION)?;
+    write_options(options, out)?;
+    write!(out, r#","document":"#)?;
+    write_document(doc, out)?;
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_request, write_string};
+    use crate::dialect::Dialect;
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+    use crate::target::Options;
+
+    #[test]
+    fn write_string_escapes_control_characters() {
+        let mut out = Vec::new();
+        write_string("a\"b\\c\nd\u{1}é", &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#""a\"b\\c\nd\u0001é""#);
+    }
+
+    #[test]
+    fn write_request_encodes_the_document() {
+        let input = "-- Doc.\n-- @query q(id: i64) -> Option<i64>\nselect x as \"x: i64\" from t where id = :id;\n";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        let doc = parser.parse_document().unwrap().resolve(input);
+        let options = Options {
+            dialect: Dialect::Postgres,
+            type_overrides: vec![("i64".to_string(), "bigint".to_string())],
+        };
+        let mut out = Vec::new();
+        write_request(&doc, &options, &mut out).unwrap();
+        let expected = concat!(
+            r#"{"version":2,"#,
+            r#""options":{"dialect":"postgres","type_overrides":[["i64","bigint"]]},"#,
+            r#""document":{"sections":[{"kind":"query","query":{"#,
+            r#""docs":[" Doc."],"#,
+            r#""annotation":{"name":"q","parameters":[{"ident":"id","type":{"kind":"simple","name":"i64"}}],"#,
+            r#""result_type":{"kind":"option","inner":{"kind":"simple","name":"i64"}}},"#,
+            r#""fragments":[{"kind":"verbatim","text":"select x as "},"#,
+            r#"{"kind":"typed_ident","text":"\"x: i64\"","ident":"x","type":{"kind":"simple","name":"i64"}},"#,
+            r#"{"kind":"verbatim","text":" from t where id = "},"#,
+            r#"{"kind":"param","text":":id","name":"id","style":"colon"},"#,
+            r#"{"kind":"verbatim","text":";"}]}},"#,
+            r#"{"kind":"verbatim","text":"\n"}]}}"#,
+            "\n",
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -27,6 +27,7 @@ pub enum Token {
 pub struct Lexer<'a> {
     input: &'a str,
     start: usize,
+    end: usize,
     state: State,
     tokens: Vec<(Token, Span)>,
 }
@@ -36,6 +37,7 @@ impl<'a> Lexer<'a> {
         Lexer {
             input: input,
             start: 0,
+            end: 0,
             state: State::Base,
             tokens: Vec::new(),
         }
@@ -64,6 +66,7 @@ impl<'a> Lexer<'a> {
     /// Lex the span until completion.
     pub fn run(&mut self, span: Span) {
         self.start = span.start;
+        self.end = span.end;
         self.state = State::Base;
 
         while self.start < span.end {
@@ -93,7 +96,7 @@ impl<'a> Lexer<'a> {
     }
 
     fn lex_base(&mut self) -> (usize, State) {
-        let input = &self.input.as_bytes()[self.start..];
+        let input = &self.input.as_bytes()[self.start..self.end];
 
         if input.len() == 0 {
             return (self.start, State::Done);
@@ -155,7 +158,7 @@ impl<'a> Lexer<'a> {
         mut include: F,
         token: Token,
     ) -> (usize, State) {
-        let input = &self.input[self.start..];
+        let input = &self.input[self.start..self.end];
 
         for (len, ch) in input.as_bytes().iter().enumerate().skip(n_skip) {
             if include(*ch) {
@@ -315,6 +318,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn lex_stops_at_end_of_span() {
+        let input = "\"id: i64\"";
+        let span = Span {
+            start: 1,
+            end: input.len() - 1,
+        };
+        let mut lexer = Lexer::new(input);
+        lexer.run(span);
+        let tokens: Vec<_> = lexer
+            .tokens()
+            .iter()
+            .map(|(token, span)| (*token, span.resolve(input)))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (Token::Ident, "id"),
+                (Token::Colon, ":"),
+                (Token::Ident, "i64")
+            ]
+        );
+    }
+
     #[test]
     fn lex_bogus_input_with_at() {
         // The fuzzer found this input to cause OOM, this is a regression test.
@@ -1,3 +1,4 @@
+use crate::dialect::Dialect;
 use crate::error::{PResult, ParseError};
 use crate::is_ascii_identifier;
 use crate::Span;
@@ -7,7 +8,12 @@ enum State {
     Base,
     InSingleQuote,
     InDoubleQuote,
+    InEscapeString,
+    InBacktickQuote,
+    InBracketQuote,
+    InDollarQuote,
     InComment,
+    InBlockComment,
     InParam,
     InSpace,
     InIdent,
@@ -21,14 +27,22 @@ pub enum Token {
     Space,
     /// A sequence of ascii alphanumeric or _, not starting with a digit.
     Ident,
-    /// A query parameter, starting with `:`.
+    /// A query parameter: `:name`, `@name`, `?`, `?NNN`, or `$NNN`.
     Param,
-    /// Content between single quotes.
+    /// Content between single quotes, including the `E` of a Postgres escape string.
     SingleQuoted,
     /// Content between double quotes.
     DoubleQuoted,
+    /// Content between backticks, a quoted identifier in MySQL and SQLite.
+    BacktickQuoted,
+    /// Content between square brackets, a quoted identifier in SQLite.
+    BracketQuoted,
+    /// Content between dollar quotes, e.g. `$$ ... $$` or `$body$ ... $body$`.
+    DollarQuoted,
     /// A comment that starts with `--` and ends at a newline (not included).
     Comment,
+    /// A comment between `/*` and `*/`, which can be nested.
+    BlockComment,
     /// `(`.
     LParen,
     /// `)`.
@@ -49,15 +63,40 @@ pub enum Token {
 
 pub struct Lexer<'a> {
     input: &'a str,
+    dialect: Dialect,
     start: usize,
     state: State,
     tokens: Vec<(Token, Span)>,
 }
 
 impl<'a> Lexer<'a> {
+    /// Create a lexer for the SQLite dialect.
     pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer::with_dialect(input, Dialect::Sqlite)
+    }
+
+    /// Create a lexer that follows the quoting rules of the given dialect.
+    ///
+    /// In all dialects, a quote inside a string is escaped by doubling it, as
+    /// in `'it''s'`. In MySQL, a backslash escapes the next character too. In
+    /// Postgres, backslash escapes apply only in escape strings, such as
+    /// `E'it\'s'`.
+    ///
+    /// MySQL and SQLite quote identifiers with backticks, and SQLite also
+    /// accepts `[name]`. In Postgres, brackets are array subscripts.
+    ///
+    /// Dollar-quoted strings, such as `$body$ ... $body$`, exist in Postgres
+    /// only. Block comments nest in Postgres only. SQLite and MySQL end a comment at
+    /// the first `*/`.
+    ///
+    /// Parameters of the form `:name` are recognized in all dialects. SQLite
+    /// accepts every parameter style, Postgres only `$NNN`, because `?` and
+    /// `@` are operators there, and MySQL only `?`, because `@name` is a user
+    /// variable there.
+    pub fn with_dialect(input: &'a str, dialect: Dialect) -> Lexer<'a> {
         Lexer {
             input: input,
+            dialect,
             start: 0,
             state: State::Base,
             tokens: Vec::new(),
@@ -104,7 +143,12 @@ impl<'a> Lexer<'a> {
                 State::Base => self.lex_base()?,
                 State::InSingleQuote => self.lex_in_single_quote()?,
                 State::InDoubleQuote => self.lex_in_double_quote()?,
+                State::InEscapeString => self.lex_in_escape_string()?,
+                State::InBacktickQuote => self.lex_in_backtick_quote()?,
+                State::InBracketQuote => self.lex_in_bracket_quote()?,
+                State::InDollarQuote => self.lex_in_dollar_quote()?,
                 State::InComment => self.lex_in_comment(),
+                State::InBlockComment => self.lex_in_block_comment()?,
                 State::InParam => self.lex_in_param(),
                 State::InSpace => self.lex_in_space(),
                 State::InIdent => self.lex_in_ident(),
@@ -141,16 +185,35 @@ impl<'a> Lexer<'a> {
         if input.starts_with(b"--") {
             return Ok((self.start, State::InComment));
         }
+        if input.starts_with(b"/*") {
+            return Ok((self.start, State::InBlockComment));
+        }
         if input.starts_with(b"'") {
             return Ok((self.start, State::InSingleQuote));
         }
+        // We only get here at the start of a token, so the `E` cannot be the
+        // end of an identifier.
+        if self.dialect == Dialect::Postgres
+            && (input.starts_with(b"E'") || input.starts_with(b"e'"))
+        {
+            return Ok((self.start, State::InEscapeString));
+        }
         if input.starts_with(b"\"") {
             return Ok((self.start, State::InDoubleQuote));
         }
+        if self.is_identifier_quote(input[0]) {
+            return match input[0] {
+                b'`' => Ok((self.start, State::InBacktickQuote)),
+                _ => Ok((self.start, State::InBracketQuote)),
+            };
+        }
+        if self.dollar_quote_tag_len(self.start).is_some() {
+            return Ok((self.start, State::InDollarQuote));
+        }
         if input[0].is_ascii_whitespace() {
             return Ok((self.start, State::InSpace));
         }
-        if input.len() > 1 && input[0] == b':' && input[1].is_ascii_alphabetic() {
+        if self.param_len(self.start).is_some() {
             return Ok((self.start, State::InParam));
         }
         if input[0].is_ascii_punctuation() {
@@ -181,20 +244,37 @@ impl<'a> Lexer<'a> {
         );
     }
 
-    fn lex_in_quote(&mut self, quote: u8, token: Token) -> PResult<(usize, State)> {
+    /// Lex a quoted string or identifier, starting `prefix_len` bytes before the
+    /// opening quote.
+    fn lex_in_quote(
+        &mut self,
+        prefix_len: usize,
+        quote: u8,
+        backslash_escapes: bool,
+        token: Token,
+    ) -> PResult<(usize, State)> {
         let input = &self.input.as_bytes()[self.start..];
 
-        // Skip over the initial opening quote.
-        for (i, &ch) in input.iter().enumerate().skip(1) {
-            // Indexing does not go out of bounds here because we start at 1.
-            if ch == quote && input[i - 1] == b'\\' {
-                // An escaped quote should not end the token.
+        // Skip over the prefix and the initial opening quote.
+        let mut i = prefix_len + 1;
+        while i < input.len() {
+            let ch = input[i];
+            if backslash_escapes && ch == b'\\' {
+                // The escaped character, which may be a quote, does not end
+                // the token.
+                i += 2;
+                continue;
+            }
+            if ch == quote && input.get(i + 1) == Some(&quote) {
+                // A doubled quote is an escaped quote.
+                i += 2;
                 continue;
             }
             if ch == quote {
                 self.push(token, i + 1);
                 return Ok((self.start + i + 1, State::Base));
             }
+            i += 1;
         }
 
         let error = ParseError {
@@ -209,11 +289,94 @@ impl<'a> Lexer<'a> {
     }
 
     fn lex_in_single_quote(&mut self) -> PResult<(usize, State)> {
-        self.lex_in_quote(b'\'', Token::SingleQuoted)
+        let backslash_escapes = self.dialect == Dialect::Mysql;
+        self.lex_in_quote(0, b'\'', backslash_escapes, Token::SingleQuoted)
     }
 
     fn lex_in_double_quote(&mut self) -> PResult<(usize, State)> {
-        self.lex_in_quote(b'"', Token::DoubleQuoted)
+        // In MySQL, double quotes delimit strings rather than identifiers, so
+        // backslash escapes apply there as well.
+        let backslash_escapes = self.dialect == Dialect::Mysql;
+        self.lex_in_quote(0, b'"', backslash_escapes, Token::DoubleQuoted)
+    }
+
+    fn lex_in_escape_string(&mut self) -> PResult<(usize, State)> {
+        self.lex_in_quote(1, b'\'', true, Token::SingleQuoted)
+    }
+
+    /// Return whether the byte opens a quoted identifier in this dialect.
+    fn is_identifier_quote(&self, ch: u8) -> bool {
+        matches!(
+            (self.dialect, ch),
+            (Dialect::Mysql, b'`') | (Dialect::Sqlite, b'`') | (Dialect::Sqlite, b'[')
+        )
+    }
+
+    fn lex_in_backtick_quote(&mut self) -> PResult<(usize, State)> {
+        self.lex_in_quote(0, b'`', false, Token::BacktickQuoted)
+    }
+
+    fn lex_in_bracket_quote(&mut self) -> PResult<(usize, State)> {
+        self.lex_in_quote(0, b']', false, Token::BracketQuoted)
+    }
+
+    /// If a dollar quote opens at `start`, return the length of its tag.
+    ///
+    /// A tag is `$`, followed by an optional identifier, followed by `$`. As
+    /// in Postgres, the identifier cannot start with a digit (that would be a
+    /// positional parameter), and a `$` that continues an identifier does not
+    /// start a dollar quote.
+    ///
+    /// Only Postgres has dollar quotes, in other dialects this returns `None`.
+    fn dollar_quote_tag_len(&self, start: usize) -> Option<usize> {
+        if self.dialect != Dialect::Postgres {
+            return None;
+        }
+        let bytes = self.input.as_bytes();
+        if bytes.get(start) != Some(&b'$') {
+            return None;
+        }
+        if start > 0 && is_ascii_identifier(bytes[start - 1]) {
+            return None;
+        }
+        let ident_len = bytes[start + 1..]
+            .iter()
+            .take_while(|ch| is_ascii_identifier(**ch))
+            .count();
+        if ident_len > 0 && bytes[start + 1].is_ascii_digit() {
+            return None;
+        }
+        match bytes.get(start + 1 + ident_len) {
+            Some(b'$') => Some(ident_len + 2),
+            _ => None,
+        }
+    }
+
+    fn lex_in_dollar_quote(&mut self) -> PResult<(usize, State)> {
+        let tag_len = self
+            .dollar_quote_tag_len(self.start)
+            .expect("Must be called with a dollar quote tag under the cursor.");
+        let tag = &self.input[self.start..self.start + tag_len];
+
+        // The string ends at the first occurrence of the same tag.
+        match self.input[self.start + tag_len..].find(tag) {
+            Some(n) => {
+                let len = tag_len + n + tag_len;
+                self.push(Token::DollarQuoted, len);
+                Ok((self.start + len, State::Base))
+            }
+            None => {
+                let error = ParseError {
+                    span: Span {
+                        start: self.start,
+                        end: self.input.len(),
+                    },
+                    message: "Unexpected end of input, dollar-quoted string is not closed.",
+                    note: None,
+                };
+                Err(error)
+            }
+        }
     }
 
     fn lex_skip_then_while<F: FnMut(u8) -> bool>(
@@ -244,8 +407,86 @@ impl<'a> Lexer<'a> {
         self.lex_while(|ch| ch != b'\n', Token::Comment)
     }
 
+    fn lex_in_block_comment(&mut self) -> PResult<(usize, State)> {
+        let input = &self.input.as_bytes()[self.start..];
+
+        // In Postgres, block comments nest, so `/* /* */ */` is a single
+        // comment, and we track the depth to find the matching end. SQLite and
+        // MySQL end the comment at the first `*/`.
+        let nests = self.dialect == Dialect::Postgres;
+        let mut depth = 0;
+        let mut i = 0;
+        while i + 1 < input.len() {
+            match &input[i..i + 2] {
+                b"/*" if nests || depth == 0 => {
+                    depth += 1;
+                    i += 2;
+                }
+                b"*/" => {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        self.push(Token::BlockComment, i);
+                        return Ok((self.start + i, State::Base));
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+
+        let error = ParseError {
+            span: Span {
+                start: self.start,
+                end: self.input.len(),
+            },
+            message: "Unexpected end of input, block comment is not closed.",
+            note: None,
+        };
+        Err(error)
+    }
+
+    /// If a query parameter starts at `start`, return its length.
+    fn param_len(&self, start: usize) -> Option<usize> {
+        let bytes = self.input.as_bytes();
+        let after_ident = start > 0 && is_ascii_identifier(bytes[start - 1]);
+        let name_len = match bytes.get(start + 1) {
+            Some(ch) if ch.is_ascii_alphabetic() => bytes[start + 1..]
+                .iter()
+                .take_while(|ch| is_ascii_identifier(**ch))
+                .count(),
+            _ => 0,
+        };
+        let digits_len = bytes[start + 1..]
+            .iter()
+            .take_while(|ch| ch.is_ascii_digit())
+            .count();
+        let len = match (bytes.get(start), self.dialect) {
+            // A `:` that follows another `:` is part of a `::` cast.
+            (Some(b':'), _) if start > 0 && bytes[start - 1] == b':' => 0,
+            (Some(b':'), _) => name_len,
+            (Some(b'@'), Dialect::Sqlite) => name_len,
+            (Some(b'?'), Dialect::Sqlite) | (Some(b'?'), Dialect::Mysql) => {
+                return Some(1 + digits_len)
+            }
+            // As for dollar quotes, a `$` that continues an identifier does
+            // not start a parameter.
+            (Some(b'$'), Dialect::Sqlite) | (Some(b'$'), Dialect::Postgres) if !after_ident => {
+                digits_len
+            }
+            _ => 0,
+        };
+        match len {
+            0 => None,
+            n => Some(1 + n),
+        }
+    }
+
     fn lex_in_param(&mut self) -> (usize, State) {
-        self.lex_skip_then_while(1, is_ascii_identifier, Token::Param)
+        let len = self
+            .param_len(self.start)
+            .expect("Must be called with a parameter under the cursor.");
+        self.push(Token::Param, len);
+        (self.start + len, State::Base)
     }
 
     fn lex_in_space(&mut self) -> (usize, State) {
@@ -271,13 +512,23 @@ impl<'a> Lexer<'a> {
             b']' => Token::RBracket,
             b';' => Token::Semicolon,
             // If it's not one of those, then we make one token until either the
-            // punctuation ends, or we do hit one of those.
+            // punctuation ends, or we do hit one of those, or a block comment,
+            // quoted identifier, dollar quote, or parameter starts.
             _ => {
                 let end_punct_chars = b"'\"(){}[];";
-                return self.lex_while(
-                    |ch| ch.is_ascii_punctuation() && !end_punct_chars.contains(&ch),
-                    Token::Punct,
-                );
+                let input = &self.input.as_bytes()[self.start..];
+                let len = (0..input.len())
+                    .take_while(|&i| {
+                        input[i].is_ascii_punctuation()
+                            && !end_punct_chars.contains(&input[i])
+                            && !input[i..].starts_with(b"/*")
+                            && !self.is_identifier_quote(input[i])
+                            && (i == 0 || self.dollar_quote_tag_len(self.start + i).is_none())
+                            && (i == 0 || self.param_len(self.start + i).is_none())
+                    })
+                    .count();
+                self.push(Token::Punct, len);
+                return (self.start + len, State::Base);
             }
         };
         self.push(token, 1);
@@ -290,7 +541,13 @@ mod test {
     use super::*;
 
     fn test_tokens(input: &str, expected_tokens: &[(Token, &str)]) {
-        let tokens = Lexer::new(input).run().expect("Failed to lex at all.");
+        test_tokens_with_dialect(input, Dialect::Sqlite, expected_tokens)
+    }
+
+    fn test_tokens_with_dialect(input: &str, dialect: Dialect, expected_tokens: &[(Token, &str)]) {
+        let tokens = Lexer::with_dialect(input, dialect)
+            .run()
+            .expect("Failed to lex at all.");
 
         for (i, expected) in expected_tokens.iter().enumerate() {
             assert!(
@@ -348,6 +605,277 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_lexes_doubled_quotes_in_standard_strings() {
+        let input = r#"'it''s' 'C:\' "a""b";"#;
+        for dialect in &[Dialect::Sqlite, Dialect::Postgres] {
+            test_tokens_with_dialect(
+                input,
+                *dialect,
+                &[
+                    (Token::SingleQuoted, "'it''s'"),
+                    (Token::Space, " "),
+                    (Token::SingleQuoted, r"'C:\'"),
+                    (Token::Space, " "),
+                    (Token::DoubleQuoted, r#""a""b""#),
+                    (Token::Semicolon, ";"),
+                ],
+            );
+        }
+    }
+
+    #[test]
+    fn it_lexes_postgres_escape_strings() {
+        let input = r"E'it\'s' e'\\' x'a' E''''";
+        test_tokens_with_dialect(
+            input,
+            Dialect::Postgres,
+            &[
+                (Token::SingleQuoted, r"E'it\'s'"),
+                (Token::Space, " "),
+                (Token::SingleQuoted, r"e'\\'"),
+                (Token::Space, " "),
+                (Token::Ident, "x"),
+                (Token::SingleQuoted, "'a'"),
+                (Token::Space, " "),
+                (Token::SingleQuoted, "E''''"),
+            ],
+        );
+        // In SQLite, there are no escape strings, and the E is an identifier.
+        test_tokens_with_dialect(
+            r"E'C:\'",
+            Dialect::Sqlite,
+            &[(Token::Ident, "E"), (Token::SingleQuoted, r"'C:\'")],
+        );
+    }
+
+    #[test]
+    fn it_lexes_mysql_backslash_escapes() {
+        let input = r#"'it\'s' 'C:\\' "a\"b" 'x''y'"#;
+        test_tokens_with_dialect(
+            input,
+            Dialect::Mysql,
+            &[
+                (Token::SingleQuoted, r"'it\'s'"),
+                (Token::Space, " "),
+                (Token::SingleQuoted, r"'C:\\'"),
+                (Token::Space, " "),
+                (Token::DoubleQuoted, r#""a\"b""#),
+                (Token::Space, " "),
+                (Token::SingleQuoted, "'x''y'"),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_lexes_quoted_identifiers_per_dialect() {
+        let input = "`a``b`=[c d].`e`";
+        test_tokens_with_dialect(
+            input,
+            Dialect::Sqlite,
+            &[
+                (Token::BacktickQuoted, "`a``b`"),
+                (Token::Punct, "="),
+                (Token::BracketQuoted, "[c d]"),
+                (Token::Punct, "."),
+                (Token::BacktickQuoted, "`e`"),
+            ],
+        );
+        test_tokens_with_dialect(
+            input,
+            Dialect::Mysql,
+            &[
+                (Token::BacktickQuoted, "`a``b`"),
+                (Token::Punct, "="),
+                (Token::LBracket, "["),
+                (Token::Ident, "c"),
+                (Token::Space, " "),
+                (Token::Ident, "d"),
+                (Token::RBracket, "]"),
+                (Token::Punct, "."),
+                (Token::BacktickQuoted, "`e`"),
+            ],
+        );
+        test_tokens_with_dialect(
+            input,
+            Dialect::Postgres,
+            &[
+                (Token::Punct, "`"),
+                (Token::Ident, "a"),
+                (Token::Punct, "``"),
+                (Token::Ident, "b"),
+                (Token::Punct, "`="),
+                (Token::LBracket, "["),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_lexes_parameters_per_dialect() {
+        let input = "x=:a,?,?12,@b,$3,c::int,d$4";
+        test_tokens_with_dialect(
+            input,
+            Dialect::Sqlite,
+            &[
+                (Token::Ident, "x"),
+                (Token::Punct, "="),
+                (Token::Param, ":a"),
+                (Token::Punct, ","),
+                (Token::Param, "?"),
+                (Token::Punct, ","),
+                (Token::Param, "?12"),
+                (Token::Punct, ","),
+                (Token::Param, "@b"),
+                (Token::Punct, ","),
+                (Token::Param, "$3"),
+                (Token::Punct, ","),
+                (Token::Ident, "c"),
+                (Token::Punct, "::"),
+                (Token::Ident, "int"),
+                (Token::Punct, ","),
+                (Token::Ident, "d"),
+                (Token::Punct, "$"),
+                (Token::Ident, "4"),
+            ],
+        );
+        test_tokens_with_dialect(
+            "a ?| b @> c = $1",
+            Dialect::Postgres,
+            &[
+                (Token::Ident, "a"),
+                (Token::Space, " "),
+                (Token::Punct, "?|"),
+                (Token::Space, " "),
+                (Token::Ident, "b"),
+                (Token::Space, " "),
+                (Token::Punct, "@>"),
+                (Token::Space, " "),
+                (Token::Ident, "c"),
+                (Token::Space, " "),
+                (Token::Punct, "="),
+                (Token::Space, " "),
+                (Token::Param, "$1"),
+            ],
+        );
+        test_tokens_with_dialect(
+            "@a=?",
+            Dialect::Mysql,
+            &[
+                (Token::Punct, "@"),
+                (Token::Ident, "a"),
+                (Token::Punct, "="),
+                (Token::Param, "?"),
+            ],
+        );
+    }
+
+    #[test]
+    fn backslash_does_not_escape_quotes_in_standard_strings() {
+        // The backslash does not escape the quote, so the string ends at `\'`,
+        // and the final quote opens a string that is not closed.
+        let input = r"x = 'it\'s' and y = 1;";
+        let error = Lexer::new(input).run().err().unwrap();
+        assert_eq!(error.span.resolve(input), "' and y = 1;");
+    }
+
+    #[test]
+    fn it_lexes_nested_block_comments_in_postgres() {
+        let input = "a/* x /* ';' */ :y */=/**/b";
+        test_tokens_with_dialect(
+            input,
+            Dialect::Postgres,
+            &[
+                (Token::Ident, "a"),
+                (Token::BlockComment, "/* x /* ';' */ :y */"),
+                (Token::Punct, "="),
+                (Token::BlockComment, "/**/"),
+                (Token::Ident, "b"),
+            ],
+        );
+    }
+
+    #[test]
+    fn block_comments_do_not_nest_in_sqlite() {
+        let input = "a/* x /* y */=:b";
+        test_tokens(
+            input,
+            &[
+                (Token::Ident, "a"),
+                (Token::BlockComment, "/* x /* y */"),
+                (Token::Punct, "="),
+                (Token::Param, ":b"),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_lexes_dollar_quoted_strings() {
+        let input = "do $$ begin x := ';'; end $$; $fn$ $$ :y $f $fn$=$$$$ a$b$ $1";
+        test_tokens_with_dialect(
+            input,
+            Dialect::Postgres,
+            &[
+                (Token::Ident, "do"),
+                (Token::Space, " "),
+                (Token::DollarQuoted, "$$ begin x := ';'; end $$"),
+                (Token::Semicolon, ";"),
+                (Token::Space, " "),
+                (Token::DollarQuoted, "$fn$ $$ :y $f $fn$"),
+                (Token::Punct, "="),
+                (Token::DollarQuoted, "$$$$"),
+                (Token::Space, " "),
+                (Token::Ident, "a"),
+                (Token::Punct, "$"),
+                (Token::Ident, "b"),
+                (Token::Punct, "$"),
+                (Token::Space, " "),
+                (Token::Param, "$1"),
+            ],
+        );
+    }
+
+    #[test]
+    fn dollar_quotes_are_postgres_only() {
+        let input = "$a$ ';' $a$$1";
+        test_tokens(
+            input,
+            &[
+                (Token::Punct, "$"),
+                (Token::Ident, "a"),
+                (Token::Punct, "$"),
+                (Token::Space, " "),
+                (Token::SingleQuoted, "';'"),
+                (Token::Space, " "),
+                (Token::Punct, "$"),
+                (Token::Ident, "a"),
+                (Token::Punct, "$"),
+                (Token::Param, "$1"),
+            ],
+        );
+    }
+
+    #[test]
+    fn unclosed_dollar_quote_results_in_error() {
+        let input = "select $body$ x $bod$";
+        let error = Lexer::with_dialect(input, Dialect::Postgres)
+            .run()
+            .err()
+            .unwrap();
+        assert_eq!(error.span.resolve(input), "$body$ x $bod$");
+        assert!(error.message.contains("dollar-quoted string is not closed"));
+    }
+
+    #[test]
+    fn unclosed_block_comment_results_in_error() {
+        let input = "select /* /* */";
+        let error = Lexer::with_dialect(input, Dialect::Postgres)
+            .run()
+            .err()
+            .unwrap();
+        assert_eq!(error.span.resolve(input), "/* /* */");
+        assert!(error.message.contains("block comment is not closed"));
+    }
+
     #[test]
     fn ascii_control_bytes_result_in_error() {
         let input = "\x01";
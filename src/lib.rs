@@ -5,8 +5,16 @@
 // you may not use this file except in compliance with the License.
 // A copy of the License has been included in the root of the repository.
 
+pub mod analysis {
+    pub mod arity;
+    pub mod params;
+    pub mod structs;
+}
 pub mod ast;
+pub mod cli;
+pub mod dialect;
 pub mod error;
+pub mod json;
 pub mod lexer {
     pub mod annotation;
     pub mod sql;
@@ -16,6 +24,7 @@ pub mod parser {
     pub mod document;
 }
 pub mod target;
+pub mod template;
 
 /// Check if a byte is part of an identifier.
 ///
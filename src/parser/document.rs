@@ -1,3 +1,4 @@
+use crate::ast::ParamKind;
 use crate::error::{PResult, ParseError};
 use crate::lexer::annotation as ann;
 use crate::lexer::sql;
@@ -72,6 +73,14 @@ impl<'a> Parser<'a> {
         self.tokens.get(self.cursor).map(|t| t.0)
     }
 
+    /// Return the first token after the one under the cursor that is not a space.
+    fn peek_after_space(&self) -> Option<sql::Token> {
+        self.tokens[self.cursor + 1..]
+            .iter()
+            .map(|t| t.0)
+            .find(|token| *token != sql::Token::Space)
+    }
+
     /// Advance the cursor by one token, consuming the token under the cursor.
     ///
     /// Returns the span of the consumed token.
@@ -159,6 +168,11 @@ impl<'a> Parser<'a> {
                     // preceding comments serve as the doc comment for the query.
                     comments.push(comment_span);
                 }
+                sql::Token::BlockComment => {
+                    // Block comments are typically file headers or commented
+                    // out code, so unlike `--` comments, they do not become
+                    // part of the doc comment of a query that follows.
+                }
                 _ => {}
             }
         }
@@ -213,6 +227,7 @@ impl<'a> Parser<'a> {
     /// to the list of fragments, and the current fragment is updated.
     fn consume_until_matching_close(
         &mut self,
+        annotation: &Annotation,
         fragments: &mut Vec<Fragment>,
         fragment: &mut Span,
     ) -> PResult<()> {
@@ -229,8 +244,6 @@ impl<'a> Parser<'a> {
         self.consume();
 
         while let Some(token) = self.peek() {
-            let span = self.tokens[self.cursor].1;
-
             if token == end_token {
                 self.consume();
                 return Ok(());
@@ -240,19 +253,12 @@ impl<'a> Parser<'a> {
                 sql::Token::LParen | sql::Token::LBrace | sql::Token::LBracket => {
                     // TODO: This might cause a stack overflow for deeply nested
                     // parens. Add some kind of depth counter to limit this.
-                    self.consume_until_matching_close(fragments, fragment)?;
+                    self.consume_until_matching_close(annotation, fragments, fragment)?;
                 }
                 sql::Token::RParen => return self.error("Found unmatched ')'."),
                 sql::Token::RBrace => return self.error("Found unmatched '}'."),
                 sql::Token::RBracket => return self.error("Found unmatched ']'."),
-                sql::Token::Param => {
-                    fragment.end = span.start;
-                    fragments.push(Fragment::Verbatim(*fragment));
-                    fragments.push(Fragment::Param(span));
-                    fragment.start = span.end;
-                    fragment.end = span.end;
-                    self.consume();
-                }
+                sql::Token::Param => self.parse_param(annotation, fragments, fragment)?,
                 sql::Token::Semicolon => {
                     // The statement ends here, but we havent' found a closing
                     // bracket yet, fall through to the end error here.
@@ -278,7 +284,69 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Skip whitespace, then parse a double quoted string as typed identifier.
+    /// Parse the parameter under the cursor, and add it to the fragments.
+    ///
+    /// The current fragment ends at the parameter, and the next one starts
+    /// after it. A positional parameter must refer to a parameter that the
+    /// annotation declares, so that backends can look it up by position.
+    fn parse_param(
+        &mut self,
+        annotation: &Annotation,
+        fragments: &mut Vec<Fragment>,
+        fragment: &mut Span,
+    ) -> PResult<()> {
+        let span = self.tokens[self.cursor].1;
+        let text = span.resolve(self.input);
+        // A number that does not fit is out of range anyway.
+        let number = || text[1..].parse::<usize>().unwrap_or(usize::MAX);
+        let kind = match text.as_bytes()[0] {
+            b':' => ParamKind::Colon,
+            b'@' => ParamKind::At,
+            b'?' if text.len() == 1 => {
+                let max_position = fragments
+                    .iter()
+                    .filter_map(|f| match f {
+                        Fragment::Param(_, kind) => kind.position(),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                ParamKind::Question(max_position + 1)
+            }
+            b'?' => ParamKind::QuestionNumbered(number()),
+            b'$' => ParamKind::Dollar(number()),
+            _ => unreachable!("The lexer only produces parameters with these prefixes."),
+        };
+
+        match kind.position() {
+            Some(n) if n == 0 => return self.error("Parameter numbers start at 1."),
+            Some(n) if n > annotation.parameters.len() => {
+                let message = match kind {
+                    ParamKind::Question(..) => {
+                        "The query has more '?' parameters than the annotation declares."
+                    }
+                    _ => "Parameter number is larger than the number of declared parameters.",
+                };
+                return self.error_with_note(
+                    message,
+                    annotation.name,
+                    "Parameters are declared here.",
+                );
+            }
+            _ => {}
+        }
+
+        fragment.end = span.start;
+        fragments.push(Fragment::Verbatim(*fragment));
+        fragments.push(Fragment::Param(span, kind));
+        fragment.start = span.end;
+        fragment.end = span.end;
+        self.consume();
+        Ok(())
+    }
+
+    /// Skip whitespace, then parse a double quoted or backtick quoted string as
+    /// typed identifier.
     ///
     /// Returns the parsed identifier and type, but also the span of the quoted
     /// string, excluding any preceding whitespace but including the quotes.
@@ -289,7 +357,7 @@ impl<'a> Parser<'a> {
 
             match token {
                 sql::Token::Space => continue,
-                sql::Token::DoubleQuoted => {
+                sql::Token::DoubleQuoted | sql::Token::BacktickQuoted => {
                     // Lex everything in between the quotes.
                     let mut lexer = ann::Lexer::new(self.input);
                     let unquoted_span = Span {
@@ -347,10 +415,17 @@ impl<'a> Parser<'a> {
                 // inside those brackets. E.g. if you have a subquery, we don't
                 // care about a "select ... as ..." inside there.
                 sql::Token::LParen | sql::Token::LBrace | sql::Token::LBracket => {
-                    self.consume_until_matching_close(&mut fragments, &mut fragment)?;
+                    self.consume_until_matching_close(&annotation, &mut fragments, &mut fragment)?;
                 }
                 sql::Token::Ident => match span.resolve(self.input) {
                     // TODO: Recognize keyword in the lexer.
+                    "as" | "AS" | "As" | "aS"
+                        if self.peek_after_space() == Some(sql::Token::DollarQuoted) =>
+                    {
+                        // In `create function ... as $$ ... $$`, the "AS"
+                        // introduces a function body, not a typed column.
+                        self.consume();
+                    }
                     "as" | "AS" | "As" | "aS" => {
                         // TODO: Due to bizarre SQL syntax, not every top-level
                         // "AS" is necessarily part of a SELECT or RETURNING,
@@ -368,12 +443,7 @@ impl<'a> Parser<'a> {
                     }
                 },
                 sql::Token::Param => {
-                    fragment.end = span.start;
-                    fragments.push(Fragment::Verbatim(fragment));
-                    fragments.push(Fragment::Param(*span));
-                    fragment.start = span.end;
-                    fragment.end = span.end;
-                    self.consume();
+                    self.parse_param(&annotation, &mut fragments, &mut fragment)?
                 }
                 sql::Token::Semicolon => {
                     // The semicolon marks the end of the query.
@@ -401,7 +471,8 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod test {
     use super::Parser;
-    use crate::ast::{Annotation, Fragment, Query, Section, Type, TypedIdent};
+    use crate::ast::{Annotation, Fragment, ParamKind, Query, Section, Type, TypedIdent};
+    use crate::dialect::Dialect;
     use crate::error::Error;
     use crate::lexer::sql::Lexer;
 
@@ -482,6 +553,144 @@ mod test {
         });
     }
 
+    #[test]
+    fn block_comments_are_verbatim_and_not_docs() {
+        let input = "
+        /* Header, with a ; and a 'quote'. */
+        -- Doc.
+        -- @query q(id: i64)
+        DELETE /* :not_a_param; */ FROM t WHERE id = :id;
+        ";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap().resolve(input);
+            let expected = Section::Query(Query {
+                docs: vec![" Doc."],
+                annotation: Annotation {
+                    name: "q",
+                    parameters: vec![TypedIdent {
+                        ident: "id",
+                        type_: Type::Simple("i64"),
+                    }],
+                    result_type: Type::Unit,
+                },
+                fragments: vec![
+                    Fragment::Verbatim("DELETE /* :not_a_param; */ FROM t WHERE id = "),
+                    Fragment::Param(":id", ParamKind::Colon),
+                    Fragment::Verbatim(";"),
+                ],
+            });
+            assert_eq!(result, expected);
+        });
+    }
+
+    #[test]
+    fn dollar_quoted_bodies_are_opaque() {
+        let input = "
+        -- @query create_touch()
+        create function touch() returns trigger as $body$
+          begin new.updated := now(); return new; end;
+        $body$ language plpgsql;
+        ";
+        let tokens = Lexer::with_dialect(input, Dialect::Postgres).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        match parser.parse_section().unwrap().resolve(input) {
+            Section::Query(query) => assert_eq!(
+                query.fragments,
+                vec![Fragment::Verbatim(
+                    "create function touch() returns trigger as $body$
+          begin new.updated := now(); return new; end;
+        $body$ language plpgsql;"
+                )],
+            ),
+            _ => panic!("Expected a query section."),
+        }
+    }
+
+    #[test]
+    fn it_parses_backtick_quoted_typed_columns_in_mysql() {
+        let input = "
+        -- @query q() -> Iterator<String>
+        select `name` as `name: String` from `users`;
+        ";
+        let tokens = Lexer::with_dialect(input, Dialect::Mysql).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        match parser.parse_section().unwrap().resolve(input) {
+            Section::Query(query) => assert_eq!(
+                query.fragments,
+                vec![
+                    Fragment::Verbatim("select `name` as "),
+                    Fragment::TypedIdent(
+                        "`name: String`",
+                        TypedIdent {
+                            ident: "name",
+                            type_: Type::Simple("String"),
+                        }
+                    ),
+                    Fragment::Verbatim(" from `users`;"),
+                ],
+            ),
+            _ => panic!("Expected a query section."),
+        }
+    }
+
+    #[test]
+    fn it_parses_positional_parameters() {
+        let input = "
+        -- @query q(a: i64, b: i64, c: i64, d: i64)
+        select ?, ?3, ?, (?1), @b, $2;
+        ";
+        with_parser(input, |p| {
+            let result = p.parse_section().unwrap().resolve(input);
+            let params: Vec<_> = match result {
+                Section::Query(query) => query
+                    .fragments
+                    .into_iter()
+                    .filter_map(|f| match f {
+                        Fragment::Param(text, kind) => Some((text, kind)),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => panic!("Expected a query section."),
+            };
+            assert_eq!(
+                params,
+                vec![
+                    ("?", ParamKind::Question(1)),
+                    ("?3", ParamKind::QuestionNumbered(3)),
+                    ("?", ParamKind::Question(4)),
+                    ("?1", ParamKind::QuestionNumbered(1)),
+                    ("@b", ParamKind::At),
+                    ("$2", ParamKind::Dollar(2)),
+                ],
+            );
+        });
+    }
+
+    #[test]
+    fn it_reports_too_many_positional_parameters() {
+        let input = "
+        -- @query get_user(id: i64) -> i64
+        select id from users where id = ? or id in (?);
+        ";
+        with_parser(input, |p| {
+            let err = p.parse_section().unwrap_err();
+            assert!(err.message.contains("more '?' parameters"));
+            assert_eq!(err.span.start, input.rfind('?').unwrap());
+            assert_eq!(err.note.unwrap().1.resolve(input), "get_user");
+        });
+    }
+
+    #[test]
+    fn it_reports_out_of_range_positional_parameters() {
+        for param in &["?0", "$0", "?3", "$99999999999999999999999"] {
+            let input = format!("-- @query q(a: i64, b: i64) -> i64\nselect ({});\n", param);
+            with_parser(&input, |p| {
+                let err = p.parse_section().unwrap_err();
+                assert_eq!(err.span.resolve(&input), *param);
+            });
+        }
+    }
+
     #[test]
     fn it_parses_a_sinple_comment_without_newline() {
         // The fuzzer found this input to cause OOM. The problem was not in the
@@ -0,0 +1,639 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type};
+use crate::target::{collect_structs, column_names, query_text, Placeholder};
+use crate::Span;
+
+/// How a value of a C type is bound and read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Int,
+    Int64,
+    Double,
+    Bool,
+    Text,
+    /// A pointer to bytes, with the length in a separate `size_t`.
+    Blob,
+    /// A type that we don't know, it is passed as `sqlite3_value`.
+    Value,
+}
+
+/// C types for the simple types that can occur in annotations.
+///
+/// Simple types that are not in this table are passed as `sqlite3_value *`.
+/// SQLite integers are signed 64-bit, so `u64` values above `INT64_MAX` are
+/// stored as negative numbers, and read back correctly.
+const TYPES: &[(&str, &str, Kind)] = &[
+    ("i8", "int8_t", Kind::Int),
+    ("i16", "int16_t", Kind::Int),
+    ("i32", "int32_t", Kind::Int),
+    ("i64", "int64_t", Kind::Int64),
+    ("u8", "uint8_t", Kind::Int),
+    ("u16", "uint16_t", Kind::Int),
+    ("u32", "uint32_t", Kind::Int64),
+    ("u64", "uint64_t", Kind::Int64),
+    ("isize", "intptr_t", Kind::Int64),
+    ("usize", "size_t", Kind::Int64),
+    ("f32", "float", Kind::Double),
+    ("f64", "double", Kind::Double),
+    ("bool", "bool", Kind::Bool),
+    ("&str", "const char *", Kind::Text),
+    ("String", "const char *", Kind::Text),
+    ("&[u8]", "const void *", Kind::Blob),
+];
+
+/// Generate C code that uses the sqlite3 C API.
+///
+/// The output is a single-header library: it contains the declarations, and
+/// the definitions are included when `QUERIES_IMPLEMENTATION` is defined. A
+/// target writes one output, so instead of a header and a source file, one
+/// source file defines `QUERIES_IMPLEMENTATION` before including the header.
+/// Queries that return rows pass them to a callback, like `sqlite3_exec`, and
+/// text in a row is valid only for the duration of the callback.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+    let queries: Vec<&Query<&str>> = doc
+        .sections
+        .iter()
+        .filter_map(|section| match section {
+            Section::Query(query) => Some(query),
+            Section::Verbatim(..) => None,
+        })
+        .collect();
+
+    writeln!(out, "#ifndef QUERIES_H")?;
+    writeln!(out, "#define QUERIES_H")?;
+    writeln!(out)?;
+    writeln!(out, "#include <stdbool.h>")?;
+    writeln!(out, "#include <stddef.h>")?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    writeln!(out, "#include <sqlite3.h>")?;
+
+    for (name, fields) in collect_structs(&doc) {
+        let fields: Vec<(&str, &Type<&str>)> = fields
+            .iter()
+            .map(|field| (field.ident, &field.type_))
+            .collect();
+        write_struct(&to_snake_case(name), &fields, out)?;
+    }
+
+    for query in &queries {
+        match row_type(&query.annotation.result_type) {
+            Type::Tuple(ts) => {
+                let columns = column_names(query);
+                let fields: Vec<(String, &Type<&str>)> = ts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, t)| match columns.get(i) {
+                        Some(column) => (column.to_string(), t),
+                        None => (format!("column{}", i), t),
+                    })
+                    .collect();
+                let fields: Vec<(&str, &Type<&str>)> =
+                    fields.iter().map(|(name, t)| (&name[..], *t)).collect();
+                write_struct(&row_struct_name(query), &fields, out)?;
+            }
+            row if needs_row_struct(row) => {
+                write_struct(&row_struct_name(query), &[("value", row)], out)?;
+            }
+            _ => {}
+        }
+    }
+
+    for query in &queries {
+        writeln!(out)?;
+        for doc_line in &query.docs {
+            writeln!(out, "//{}", doc_line)?;
+        }
+        write_signature(query, out)?;
+        writeln!(out, ";")?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "#endif /* QUERIES_H */")?;
+    writeln!(out)?;
+    writeln!(out, "#ifdef QUERIES_IMPLEMENTATION")?;
+
+    for query in &queries {
+        writeln!(out)?;
+        write_definition(query, out)?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "#endif /* QUERIES_IMPLEMENTATION */")
+}
+
+/// Convert a PascalCase struct name into snake_case.
+fn to_snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    for ch in name.chars() {
+        if ch.is_ascii_uppercase() {
+            if !result.is_empty() {
+                result.push('_');
+            }
+            result.push(ch.to_ascii_lowercase());
+        } else {
+            result.push(ch);
+        }
+    }
+    result
+}
+
+/// Return the type of the rows that the query returns.
+fn row_type<'a, 'b>(result_type: &'b Type<&'a str>) -> &'b Type<&'a str> {
+    match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => inner,
+        other => other,
+    }
+}
+
+/// The name of the struct that holds a tuple row or nullable row.
+///
+/// C has no tuples, and a nullable value needs a null indicator next to it.
+fn row_struct_name(query: &Query<&str>) -> String {
+    format!("{}_row", query.annotation.name)
+}
+
+/// Return whether the type is a blob, which needs a length next to the pointer.
+fn is_blob(type_: &Type<&str>) -> bool {
+    match type_ {
+        Type::Option(inner) => is_blob(inner),
+        Type::Simple(t) => lookup(t).1 == Kind::Blob,
+        _ => false,
+    }
+}
+
+/// Return whether a row that is not a tuple needs a struct anyway.
+///
+/// A nullable value needs a null indicator, and a blob needs a length.
+fn needs_row_struct(row: &Type<&str>) -> bool {
+    matches!(row, Type::Option(..)) || is_blob(row)
+}
+
+/// Look up the C type and kind of a simple type.
+fn lookup(type_: &str) -> (&'static str, Kind) {
+    match TYPES.iter().find(|(rust, _, _)| *rust == type_) {
+        Some((_, c, kind)) => (c, *kind),
+        None => ("sqlite3_value *", Kind::Value),
+    }
+}
+
+/// Format a type as a C type, followed by `name`.
+///
+/// Options need a null indicator next to the value, the caller handles those.
+fn format_declaration(type_: &Type<&str>, name: &str) -> String {
+    let c_type = match type_ {
+        Type::Simple(t) => lookup(t).0.to_string(),
+        Type::Option(inner) => return format_declaration(inner, name),
+        Type::Struct(struct_name, _fields) => format!("struct {}", to_snake_case(struct_name)),
+        _ => "sqlite3_value *".to_string(),
+    };
+    if c_type.ends_with('*') {
+        format!("{}{}", c_type, name)
+    } else {
+        format!("{} {}", c_type, name)
+    }
+}
+
+fn write_struct(
+    name: &str,
+    fields: &[(&str, &Type<&str>)],
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    writeln!(out)?;
+    writeln!(out, "struct {} {{", name)?;
+    for (field_name, field_type) in fields {
+        writeln!(out, "    {};", format_declaration(field_type, field_name))?;
+        if is_blob(field_type) {
+            writeln!(out, "    size_t {}_len;", field_name)?;
+        }
+        if let Type::Option(..) = field_type {
+            writeln!(out, "    bool {}_is_null;", field_name)?;
+        }
+    }
+    writeln!(out, "}};")
+}
+
+/// Write the function signature, without trailing semicolon or body.
+fn write_signature(query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    let annotation = &query.annotation;
+    write!(out, "int {}(sqlite3 *db", annotation.name)?;
+    for param in &annotation.parameters {
+        write!(out, ", {}", format_declaration(&param.type_, param.ident))?;
+        if is_blob(&param.type_) {
+            write!(out, ", size_t {}_len", param.ident)?;
+        }
+        if let Type::Option(..) = param.type_ {
+            write!(out, ", bool {}_is_null", param.ident)?;
+        }
+    }
+    match row_type(&annotation.result_type) {
+        Type::Unit => {}
+        row if matches!(row, Type::Tuple(..)) || needs_row_struct(row) => write!(
+            out,
+            ", int (*on_row)(const struct {} *row, void *user_data), void *user_data",
+            row_struct_name(query)
+        )?,
+        other => {
+            // Text is `const` already, it does not need the qualifier twice.
+            let row = format_declaration(other, "*row");
+            let row = if row.starts_with("const ") {
+                row
+            } else {
+                format!("const {}", row)
+            };
+            write!(
+                out,
+                ", int (*on_row)({}, void *user_data), void *user_data",
+                row
+            )?
+        }
+    }
+    write!(out, ")")
+}
+
+/// Format the query as a C string literal, one line per line of the query.
+fn write_string_literal(text: &str, indent: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    let lines: Vec<&str> = text.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        let escaped = line.replace('\\', "\\\\").replace('"', "\\\"");
+        let newline = if i + 1 < lines.len() { "\\n" } else { "" };
+        write!(out, "\n{}\"{}{}\"", indent, escaped, newline)?;
+    }
+    Ok(())
+}
+
+/// Write the statements that bind a parameter at the 1-based `index`.
+fn write_bind(
+    type_: &Type<&str>,
+    index: usize,
+    name: &str,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let (inner, nullable) = match type_ {
+        Type::Option(inner) => (&**inner, true),
+        other => (other, false),
+    };
+    let kind = match inner {
+        Type::Simple(t) => lookup(t).1,
+        _ => Kind::Value,
+    };
+    let bind = match kind {
+        Kind::Int | Kind::Bool => format!("sqlite3_bind_int(stmt, {}, {})", index, name),
+        Kind::Int64 => format!("sqlite3_bind_int64(stmt, {}, {})", index, name),
+        Kind::Double => format!("sqlite3_bind_double(stmt, {}, {})", index, name),
+        Kind::Text => format!(
+            "sqlite3_bind_text(stmt, {}, {}, -1, SQLITE_TRANSIENT)",
+            index, name
+        ),
+        Kind::Blob => format!(
+            "sqlite3_bind_blob64(stmt, {}, {}, {}_len, SQLITE_TRANSIENT)",
+            index, name, name
+        ),
+        Kind::Value => format!("sqlite3_bind_value(stmt, {}, {})", index, name),
+    };
+    if nullable {
+        writeln!(out, "    if ({}_is_null) {{", name)?;
+        writeln!(out, "        rc = sqlite3_bind_null(stmt, {});", index)?;
+        writeln!(out, "    }} else {{")?;
+        writeln!(out, "        rc = {};", bind)?;
+        writeln!(out, "    }}")?;
+    } else {
+        writeln!(out, "    rc = {};", bind)?;
+    }
+    writeln!(out, "    if (rc != SQLITE_OK) goto done;")
+}
+
+/// Write the statements that read the 0-based column `index` into `target`.
+fn write_read_column(
+    type_: &Type<&str>,
+    index: usize,
+    target: &str,
+    indent: &str,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let inner = match type_ {
+        Type::Option(inner) => {
+            writeln!(
+                out,
+                "{}{}_is_null = sqlite3_column_type(stmt, {}) == SQLITE_NULL;",
+                indent, target, index
+            )?;
+            &**inner
+        }
+        other => other,
+    };
+    let kind = match inner {
+        Type::Simple(t) => lookup(t).1,
+        _ => Kind::Value,
+    };
+    let read = match kind {
+        Kind::Int => format!("sqlite3_column_int(stmt, {})", index),
+        Kind::Int64 => format!("sqlite3_column_int64(stmt, {})", index),
+        Kind::Double => format!("sqlite3_column_double(stmt, {})", index),
+        Kind::Bool => format!("sqlite3_column_int(stmt, {}) != 0", index),
+        Kind::Text => format!("(const char *)sqlite3_column_text(stmt, {})", index),
+        Kind::Blob => format!("sqlite3_column_blob(stmt, {})", index),
+        Kind::Value => format!("sqlite3_column_value(stmt, {})", index),
+    };
+    writeln!(out, "{}{} = {};", indent, target, read)?;
+    if kind == Kind::Blob {
+        // The length is only valid after reading the blob.
+        writeln!(
+            out,
+            "{}{}_len = sqlite3_column_bytes(stmt, {});",
+            indent, target, index
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the statements that read the current row and pass it to `on_row`.
+fn write_read_row(query: &Query<&str>, indent: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    let row_type = row_type(&query.annotation.result_type);
+    match row_type {
+        Type::Struct(name, fields) => {
+            writeln!(out, "{}struct {} row;", indent, to_snake_case(name))?;
+            for (i, field) in fields.iter().enumerate() {
+                let target = format!("row.{}", field.ident);
+                write_read_column(&field.type_, i, &target, indent, out)?;
+            }
+        }
+        Type::Tuple(ts) => {
+            let columns = column_names(query);
+            writeln!(out, "{}struct {} row;", indent, row_struct_name(query))?;
+            for (i, t) in ts.iter().enumerate() {
+                let target = match columns.get(i) {
+                    Some(column) => format!("row.{}", column),
+                    None => format!("row.column{}", i),
+                };
+                write_read_column(t, i, &target, indent, out)?;
+            }
+        }
+        row if needs_row_struct(row) => {
+            writeln!(out, "{}struct {} row;", indent, row_struct_name(query))?;
+            write_read_column(row, 0, "row.value", indent, out)?;
+        }
+        other => {
+            writeln!(out, "{}{};", indent, format_declaration(other, "row"))?;
+            write_read_column(other, 0, "row", indent, out)?;
+        }
+    }
+    writeln!(out, "{}if (on_row(&row, user_data) != 0) {{", indent)?;
+    writeln!(out, "{}    rc = SQLITE_ABORT;", indent)?;
+    writeln!(out, "{}    goto done;", indent)?;
+    writeln!(out, "{}}}", indent)
+}
+
+fn write_definition(query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    let annotation = &query.annotation;
+    write_signature(query, out)?;
+    writeln!(out)?;
+    writeln!(out, "{{")?;
+
+    let (text, params) = query_text(query, Placeholder::Named);
+    write!(out, "    static const char sql[] =")?;
+    write_string_literal(&text, "        ", out)?;
+    writeln!(out, ";")?;
+    writeln!(out, "    sqlite3_stmt *stmt;")?;
+    writeln!(
+        out,
+        "    int rc = sqlite3_prepare_v3(db, sql, sizeof(sql), 0, &stmt, NULL);"
+    )?;
+    writeln!(out, "    if (rc != SQLITE_OK) return rc;")?;
+
+    // SQLite numbers named parameters in order of first occurrence.
+    for (i, name) in params.iter().enumerate() {
+        let declared = annotation
+            .parameters
+            .iter()
+            .find(|param| param.ident == *name);
+        let type_ = match declared {
+            Some(param) => &param.type_,
+            None => &Type::Unit,
+        };
+        write_bind(type_, i + 1, name, out)?;
+    }
+
+    match &annotation.result_type {
+        Type::Unit => {
+            writeln!(
+                out,
+                "    while ((rc = sqlite3_step(stmt)) == SQLITE_ROW) {{}}"
+            )?;
+            writeln!(out, "    if (rc == SQLITE_DONE) rc = SQLITE_OK;")?;
+        }
+        Type::Iterator(..) => {
+            writeln!(
+                out,
+                "    while ((rc = sqlite3_step(stmt)) == SQLITE_ROW) {{"
+            )?;
+            write_read_row(query, "        ", out)?;
+            writeln!(out, "    }}")?;
+            writeln!(out, "    if (rc == SQLITE_DONE) rc = SQLITE_OK;")?;
+        }
+        result_type => {
+            // Without a row, an option is fine, but a single row is missing.
+            let no_row = match result_type {
+                Type::Option(..) => "SQLITE_OK",
+                _ => "SQLITE_NOTFOUND",
+            };
+            writeln!(out, "    rc = sqlite3_step(stmt);")?;
+            writeln!(out, "    if (rc == SQLITE_ROW) {{")?;
+            write_read_row(query, "        ", out)?;
+            writeln!(out, "        rc = SQLITE_OK;")?;
+            writeln!(out, "    }} else if (rc == SQLITE_DONE) {{")?;
+            writeln!(out, "        rc = {};", no_row)?;
+            writeln!(out, "    }}")?;
+        }
+    }
+
+    // Binding parameters and handling rows can jump to the cleanup.
+    let has_goto = !params.is_empty() || annotation.result_type != Type::Unit;
+    if has_goto {
+        writeln!(out, "done:")?;
+    }
+    writeln!(out, "    sqlite3_finalize(stmt);")?;
+    writeln!(out, "    return rc;")?;
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{process_file, to_snake_case};
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn to_snake_case_splits_words() {
+        assert_eq!(to_snake_case("User"), "user");
+        assert_eq!(to_snake_case("NewUser"), "new_user");
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: Option<String>"
+from users
+where name = :name;
+"#;
+        let expected = r#"#ifndef QUERIES_H
+#define QUERIES_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include <sqlite3.h>
+
+struct user {
+    int64_t id;
+    const char *name;
+    const char *email;
+    bool email_is_null;
+};
+
+// Look up a user by username.
+int get_user_by_name(sqlite3 *db, const char *name, int (*on_row)(const struct user *row, void *user_data), void *user_data);
+
+#endif /* QUERIES_H */
+
+#ifdef QUERIES_IMPLEMENTATION
+
+int get_user_by_name(sqlite3 *db, const char *name, int (*on_row)(const struct user *row, void *user_data), void *user_data)
+{
+    static const char sql[] =
+        "select id as id, name as name, email as email\n"
+        "from users\n"
+        "where name = :name;";
+    sqlite3_stmt *stmt;
+    int rc = sqlite3_prepare_v3(db, sql, sizeof(sql), 0, &stmt, NULL);
+    if (rc != SQLITE_OK) return rc;
+    rc = sqlite3_bind_text(stmt, 1, name, -1, SQLITE_TRANSIENT);
+    if (rc != SQLITE_OK) goto done;
+    rc = sqlite3_step(stmt);
+    if (rc == SQLITE_ROW) {
+        struct user row;
+        row.id = sqlite3_column_int64(stmt, 0);
+        row.name = (const char *)sqlite3_column_text(stmt, 1);
+        row.email_is_null = sqlite3_column_type(stmt, 2) == SQLITE_NULL;
+        row.email = (const char *)sqlite3_column_text(stmt, 2);
+        if (on_row(&row, user_data) != 0) {
+            rc = SQLITE_ABORT;
+            goto done;
+        }
+        rc = SQLITE_OK;
+    } else if (rc == SQLITE_DONE) {
+        rc = SQLITE_OK;
+    }
+done:
+    sqlite3_finalize(stmt);
+    return rc;
+}
+
+#endif /* QUERIES_IMPLEMENTATION */
+"#;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_uses_null_flags_and_row_structs_for_tuples() {
+        let input = "
+        -- @query get_range(low: i64, high: Option<f64>) -> Iterator<(i64, Option<String>)>
+        select id as \"id: i64\", name as \"name: Option<String>\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input);
+        assert!(output.contains(
+            "struct get_range_row {\n    int64_t id;\n    const char *name;\n    bool name_is_null;\n};"
+        ));
+        assert!(output.contains(
+            "int get_range(sqlite3 *db, int64_t low, double high, bool high_is_null, int (*on_row)(const struct get_range_row *row, void *user_data), void *user_data);"
+        ));
+        assert!(output.contains("    rc = sqlite3_bind_int64(stmt, 1, low);\n"));
+        assert!(output.contains(
+            "    if (high_is_null) {\n        rc = sqlite3_bind_null(stmt, 2);\n    } else {\n        rc = sqlite3_bind_double(stmt, 2, high);\n    }\n"
+        ));
+        assert!(!output.contains("sqlite3_bind_int64(stmt, 3"));
+        assert!(output.contains("    while ((rc = sqlite3_step(stmt)) == SQLITE_ROW) {\n        struct get_range_row row;\n"));
+    }
+
+    #[test]
+    fn it_uses_a_row_struct_for_nullable_scalars() {
+        let input = "
+        -- @query get_ages() -> Iterator<Option<i64>>
+        select age from users;
+        ";
+        let output = generate(input);
+        assert!(output
+            .contains("struct get_ages_row {\n    int64_t value;\n    bool value_is_null;\n};"));
+        assert!(output.contains(
+            "int get_ages(sqlite3 *db, int (*on_row)(const struct get_ages_row *row, void *user_data), void *user_data);"
+        ));
+        assert!(output.contains(concat!(
+            "        struct get_ages_row row;\n",
+            "        row.value_is_null = sqlite3_column_type(stmt, 0) == SQLITE_NULL;\n",
+            "        row.value = sqlite3_column_int64(stmt, 0);\n",
+        )));
+    }
+
+    #[test]
+    fn it_binds_wide_integers_and_blobs() {
+        let input = "
+        -- @query put(id: u64, n: usize, i: isize, data: Option<&[u8]>) -> &[u8]
+        select data as \"data: &[u8]\" from t where id = :id and n = :n and i = :i and data = :data;
+        ";
+        let output = generate(input);
+        assert!(
+            output.contains("struct put_row {\n    const void *value;\n    size_t value_len;\n};")
+        );
+        assert!(output.contains(
+            "int put(sqlite3 *db, uint64_t id, size_t n, intptr_t i, const void *data, size_t data_len, bool data_is_null, int (*on_row)(const struct put_row *row, void *user_data), void *user_data);"
+        ));
+        assert!(output.contains("    rc = sqlite3_bind_int64(stmt, 1, id);\n"));
+        assert!(output.contains("    rc = sqlite3_bind_int64(stmt, 2, n);\n"));
+        assert!(output.contains("    rc = sqlite3_bind_int64(stmt, 3, i);\n"));
+        assert!(output.contains(
+            "        rc = sqlite3_bind_blob64(stmt, 4, data, data_len, SQLITE_TRANSIENT);\n"
+        ));
+        assert!(output.contains(concat!(
+            "        row.value = sqlite3_column_blob(stmt, 0);\n",
+            "        row.value_len = sqlite3_column_bytes(stmt, 0);\n",
+        )));
+    }
+
+    #[test]
+    fn it_reports_a_missing_row_for_scalars() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input);
+        assert!(output.contains("int setup_schema(sqlite3 *db);\n"));
+        assert!(output.contains(
+            "int add_user(sqlite3 *db, const char *name, const char *email, int (*on_row)(const int64_t *row, void *user_data), void *user_data);"
+        ));
+        assert!(
+            output.contains("        int64_t row;\n        row = sqlite3_column_int64(stmt, 0);\n")
+        );
+        assert!(output.contains("        rc = SQLITE_NOTFOUND;\n"));
+        assert!(output.contains("struct new_user {\n"));
+
+        let input = "-- @query get_name() -> String\nselect name from t;";
+        let output = generate(input);
+        assert!(output.contains("int (*on_row)(const char **row, void *user_data)"));
+    }
+}
@@ -0,0 +1,366 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type};
+use crate::dialect::Dialect;
+use crate::target::{collect_structs, column_names, query_text, to_camel_case, Placeholder};
+use crate::Span;
+
+/// Go types for the simple types that can occur in annotations.
+///
+/// The third element is the `database/sql` type for a nullable value, if the
+/// package has one. Other nullable values become pointers. Simple types that
+/// are not in this table are used as-is.
+const TYPES: &[(&str, &str, Option<&str>)] = &[
+    ("i8", "int8", None),
+    ("i16", "int16", Some("sql.NullInt16")),
+    ("i32", "int32", Some("sql.NullInt32")),
+    ("i64", "int64", Some("sql.NullInt64")),
+    ("u8", "uint8", Some("sql.NullByte")),
+    ("u16", "uint16", None),
+    ("u32", "uint32", None),
+    ("u64", "uint64", None),
+    ("isize", "int", None),
+    ("usize", "uint", None),
+    ("f32", "float32", None),
+    ("f64", "float64", Some("sql.NullFloat64")),
+    ("bool", "bool", Some("sql.NullBool")),
+    ("&str", "string", Some("sql.NullString")),
+    ("String", "string", Some("sql.NullString")),
+    ("&[u8]", "[]byte", None),
+];
+
+/// Generate a Go package that uses `database/sql`.
+///
+/// Go drivers do not support named parameters, so parameters are rewritten to
+/// `?` or `$n`, depending on the dialect.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    dialect: Dialect,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+    let placeholder = match dialect {
+        Dialect::Sqlite => Placeholder::Question,
+        Dialect::Postgres => Placeholder::Dollar,
+        Dialect::Mysql => Placeholder::Question,
+    };
+
+    writeln!(out, "package queries")?;
+    writeln!(out)?;
+    writeln!(out, "import (")?;
+    writeln!(out, "\t\"context\"")?;
+    writeln!(out, "\t\"database/sql\"")?;
+    writeln!(out, ")")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "// DBTX is the part of *sql.DB and *sql.Tx that the queries need."
+    )?;
+    writeln!(out, "type DBTX interface {{")?;
+    writeln!(out, "\tExecContext(ctx context.Context, query string, args ...interface{{}}) (sql.Result, error)")?;
+    writeln!(out, "\tQueryContext(ctx context.Context, query string, args ...interface{{}}) (*sql.Rows, error)")?;
+    writeln!(
+        out,
+        "\tQueryRowContext(ctx context.Context, query string, args ...interface{{}}) *sql.Row"
+    )?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "type Queries struct {{")?;
+    writeln!(out, "\tdb DBTX")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "func New(db DBTX) *Queries {{")?;
+    writeln!(out, "\treturn &Queries{{db: db}}")?;
+    writeln!(out, "}}")?;
+
+    for (name, fields) in collect_structs(&doc) {
+        let fields: Vec<(String, String)> = fields
+            .iter()
+            .map(|field| (to_camel_case(field.ident, true), format_type(&field.type_)))
+            .collect();
+        write_struct(name, &fields, out)?;
+    }
+
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            write_query(query, placeholder, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Format a type as a Go type.
+///
+/// Tuples have no counterpart in Go, results that are tuples get a struct.
+fn format_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Unit => "struct{}".to_string(),
+        Type::Simple(t) => match TYPES.iter().find(|(rust, _, _)| rust == t) {
+            Some((_, go, _)) => go.to_string(),
+            None => t.to_string(),
+        },
+        Type::Iterator(t) => format!("[]{}", format_type(t)),
+        Type::Option(t) => match &**t {
+            Type::Simple(s) => match TYPES.iter().find(|(rust, _, _)| rust == s) {
+                Some((_, _, Some(null_type))) => null_type.to_string(),
+                _ => format!("*{}", format_type(t)),
+            },
+            _ => format!("*{}", format_type(t)),
+        },
+        Type::Tuple(ts) => {
+            let fields: Vec<String> = ts
+                .iter()
+                .enumerate()
+                .map(|(i, t)| format!("Field{} {}", i, format_type(t)))
+                .collect();
+            format!("struct{{ {} }}", fields.join("; "))
+        }
+        Type::Struct(name, _fields) => name.to_string(),
+    }
+}
+
+/// Format the query as a Go string literal.
+fn format_string(text: &str) -> String {
+    // A raw string cannot contain a backtick, so we splice those in.
+    format!("`{}`", text.replace('`', "` + \"`\" + `"))
+}
+
+/// Write a struct type, with the field types aligned the way gofmt does.
+fn write_struct(
+    name: &str,
+    fields: &[(String, String)],
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    writeln!(out)?;
+    writeln!(out, "type {} struct {{", name)?;
+    for (field_name, field_type) in fields {
+        writeln!(out, "\t{:width$} {}", field_name, field_type, width = width)?;
+    }
+    writeln!(out, "}}")
+}
+
+fn write_query(
+    query: &Query<&str>,
+    placeholder: Placeholder,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let annotation = &query.annotation;
+    let method_name = to_camel_case(annotation.name, true);
+    let const_name = format!("{}Query", to_camel_case(annotation.name, false));
+    let result_type = &annotation.result_type;
+
+    let (text, params) = query_text(query, placeholder);
+    writeln!(out)?;
+    writeln!(out, "const {} = {}", const_name, format_string(&text))?;
+
+    let row_type = match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => &**inner,
+        other => other,
+    };
+
+    // Go has no tuples, so we name the typed columns in a struct instead.
+    // Scalars get scanned directly, the others are scanned field by field.
+    let mut fields: Vec<String> = Vec::new();
+    let row_type_name = match row_type {
+        Type::Unit => String::new(),
+        Type::Struct(name, struct_fields) => {
+            for field in struct_fields {
+                fields.push(to_camel_case(field.ident, true));
+            }
+            name.to_string()
+        }
+        Type::Tuple(ts) => {
+            let columns = column_names(query);
+            let mut row_fields = Vec::new();
+            for (i, t) in ts.iter().enumerate() {
+                let field_name = match columns.get(i) {
+                    Some(column) => to_camel_case(column, true),
+                    None => format!("Column{}", i),
+                };
+                fields.push(field_name.clone());
+                row_fields.push((field_name, format_type(t)));
+            }
+            let name = format!("{}Row", method_name);
+            write_struct(&name, &row_fields, out)?;
+            name
+        }
+        other => format_type(other),
+    };
+
+    writeln!(out)?;
+    for doc_line in &query.docs {
+        writeln!(out, "//{}", doc_line)?;
+    }
+    write!(out, "func (q *Queries) {}(ctx context.Context", method_name)?;
+    for param in &annotation.parameters {
+        write!(
+            out,
+            ", {} {}",
+            to_camel_case(param.ident, false),
+            format_type(&param.type_)
+        )?;
+    }
+    match result_type {
+        Type::Unit => writeln!(out, ") error {{")?,
+        Type::Option(..) => writeln!(out, ") (*{}, error) {{", row_type_name)?,
+        Type::Iterator(..) => writeln!(out, ") ([]{}, error) {{", row_type_name)?,
+        _ => writeln!(out, ") ({}, error) {{", row_type_name)?,
+    }
+
+    let mut args = const_name;
+    for param in &params {
+        args.push_str(", ");
+        args.push_str(&to_camel_case(param, false));
+    }
+
+    let scan_args = |var: &str| -> String {
+        match fields.len() {
+            0 => format!("&{}", var),
+            _ => {
+                let refs: Vec<String> = fields
+                    .iter()
+                    .map(|field| format!("&{}.{}", var, field))
+                    .collect();
+                refs.join(", ")
+            }
+        }
+    };
+
+    match result_type {
+        Type::Unit => {
+            writeln!(out, "\t_, err := q.db.ExecContext(ctx, {})", args)?;
+            writeln!(out, "\treturn err")?;
+        }
+        Type::Option(..) => {
+            writeln!(out, "\trow := q.db.QueryRowContext(ctx, {})", args)?;
+            writeln!(out, "\tvar result {}", row_type_name)?;
+            writeln!(out, "\terr := row.Scan({})", scan_args("result"))?;
+            writeln!(out, "\tif err == sql.ErrNoRows {{")?;
+            writeln!(out, "\t\treturn nil, nil")?;
+            writeln!(out, "\t}}")?;
+            writeln!(out, "\tif err != nil {{")?;
+            writeln!(out, "\t\treturn nil, err")?;
+            writeln!(out, "\t}}")?;
+            writeln!(out, "\treturn &result, nil")?;
+        }
+        Type::Iterator(..) => {
+            writeln!(out, "\trows, err := q.db.QueryContext(ctx, {})", args)?;
+            writeln!(out, "\tif err != nil {{")?;
+            writeln!(out, "\t\treturn nil, err")?;
+            writeln!(out, "\t}}")?;
+            writeln!(out, "\tdefer rows.Close()")?;
+            writeln!(out, "\tvar result []{}", row_type_name)?;
+            writeln!(out, "\tfor rows.Next() {{")?;
+            writeln!(out, "\t\tvar item {}", row_type_name)?;
+            writeln!(
+                out,
+                "\t\tif err := rows.Scan({}); err != nil {{",
+                scan_args("item")
+            )?;
+            writeln!(out, "\t\t\treturn nil, err")?;
+            writeln!(out, "\t\t}}")?;
+            writeln!(out, "\t\tresult = append(result, item)")?;
+            writeln!(out, "\t}}")?;
+            writeln!(out, "\treturn result, rows.Err()")?;
+        }
+        _ => {
+            writeln!(out, "\trow := q.db.QueryRowContext(ctx, {})", args)?;
+            writeln!(out, "\tvar result {}", row_type_name)?;
+            writeln!(out, "\terr := row.Scan({})", scan_args("result"))?;
+            writeln!(out, "\treturn result, err")?;
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::dialect::Dialect;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str, dialect: Dialect) -> String {
+        generate_for_test(input, |input, doc, out| {
+            process_file(input, doc, dialect, out)
+        })
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: Option<String>"
+from users
+where name = :name;
+"#;
+        let output = generate(input, Dialect::Sqlite);
+        let expected = "
+type User struct {
+\tId    int64
+\tName  string
+\tEmail sql.NullString
+}
+
+const getUserByNameQuery = `select id as id, name as name, email as email
+from users
+where name = ?;`
+
+// Look up a user by username.
+func (q *Queries) GetUserByName(ctx context.Context, name string) (*User, error) {
+\trow := q.db.QueryRowContext(ctx, getUserByNameQuery, name)
+\tvar result User
+\terr := row.Scan(&result.Id, &result.Name, &result.Email)
+\tif err == sql.ErrNoRows {
+\t\treturn nil, nil
+\t}
+\tif err != nil {
+\t\treturn nil, err
+\t}
+\treturn &result, nil
+}
+";
+        assert!(output.starts_with("package queries\n"));
+        assert!(output.ends_with(expected), "{}", output);
+    }
+
+    #[test]
+    fn it_binds_every_occurrence_for_question_marks() {
+        let input = "
+        -- @query get_range(low: i64, high: i64) -> Iterator<(i64, String)>
+        select id as \"id: i64\", name as \"name: String\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input, Dialect::Sqlite);
+        assert!(output.contains("where id >= ? and id < ? and id <> ?;`"));
+        assert!(output.contains("q.db.QueryContext(ctx, getRangeQuery, low, high, low)"));
+        assert!(output.contains("type GetRangeRow struct {\n\tId   int64\n\tName string\n}"));
+        assert!(output.contains(") ([]GetRangeRow, error) {"));
+        assert!(output.contains("\t\tif err := rows.Scan(&item.Id, &item.Name); err != nil {"));
+
+        let output = generate(input, Dialect::Postgres);
+        assert!(output.contains("where id >= $1 and id < $2 and id <> $1;`"));
+        assert!(output.contains("q.db.QueryContext(ctx, getRangeQuery, low, high)"));
+    }
+
+    #[test]
+    fn it_splices_backticks_into_the_query() {
+        let input = "
+        -- @query count_users() -> i64
+        select count(*) from `users`;
+        ";
+        let output = generate(input, Dialect::Sqlite);
+        assert!(output.contains("select count(*) from ` + \"`\" + `users` + \"`\" + `;`"));
+        assert!(output.contains("\tvar result int64\n\terr := row.Scan(&result)\n"));
+    }
+}
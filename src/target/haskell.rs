@@ -0,0 +1,374 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type};
+use crate::dialect::Dialect;
+use crate::target::{collect_structs, query_text, to_camel_case, unsupported_dialect, Placeholder};
+use crate::Span;
+
+/// Haskell types for the simple types that can occur in annotations.
+///
+/// The elements are the annotation type, the Haskell type, and the module that
+/// exports it, if it is not in the Prelude. Simple types that are not in this
+/// table are used as-is.
+const TYPES: &[(&str, &str, Option<&str>)] = &[
+    ("i8", "Int8", Some("Data.Int")),
+    ("i16", "Int16", Some("Data.Int")),
+    ("i32", "Int32", Some("Data.Int")),
+    ("i64", "Int64", Some("Data.Int")),
+    ("u8", "Word8", Some("Data.Word")),
+    ("u16", "Word16", Some("Data.Word")),
+    ("u32", "Word32", Some("Data.Word")),
+    ("u64", "Word64", Some("Data.Word")),
+    ("f32", "Float", None),
+    ("f64", "Double", None),
+    ("bool", "Bool", None),
+    ("&str", "Text", Some("Data.Text")),
+    ("String", "Text", Some("Data.Text")),
+    ("&[u8]", "ByteString", Some("Data.ByteString")),
+];
+
+/// Generate a Haskell module for `sqlite-simple` or `postgresql-simple`.
+///
+/// Both libraries use positional `?` placeholders, so a parameter that occurs
+/// more than once is passed once per occurrence.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    dialect: Dialect,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+    let structs = collect_structs(&doc);
+    let queries: Vec<&Query<&str>> = doc
+        .sections
+        .iter()
+        .filter_map(|section| match section {
+            Section::Query(query) => Some(query),
+            Section::Verbatim(..) => None,
+        })
+        .collect();
+
+    // Import only what we use, to not trigger warnings about unused imports.
+    let mut imports: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let (library, from_row) = match dialect {
+        Dialect::Sqlite => ("Database.SQLite.Simple", "Database.SQLite.Simple.FromRow"),
+        Dialect::Postgres => (
+            "Database.PostgreSQL.Simple",
+            "Database.PostgreSQL.Simple.FromRow",
+        ),
+        // The mysql-simple package has no `FromRow` with `field`.
+        Dialect::Mysql => return Err(unsupported_dialect("haskell", dialect)),
+    };
+    imports.entry(library).or_default().insert("Connection");
+    for (_name, fields) in &structs {
+        for field in fields.iter() {
+            collect_imports(&field.type_, &mut imports);
+        }
+    }
+    for query in &queries {
+        let annotation = &query.annotation;
+        collect_imports(&annotation.result_type, &mut imports);
+        for param in &annotation.parameters {
+            collect_imports(&param.type_, &mut imports);
+        }
+        let (_text, params) = query_text(query, Placeholder::Question);
+        let functions = imports.entry(library).or_default();
+        match (&annotation.result_type, params.len()) {
+            (Type::Unit, 0) => functions.insert("execute_"),
+            (Type::Unit, _) => functions.insert("execute"),
+            (_, 0) => functions.insert("query_"),
+            (_, _) => functions.insert("query"),
+        };
+        if params.len() == 1 || is_scalar(row_type(&annotation.result_type)) {
+            functions.insert("Only (..)");
+        }
+        if let Type::Option(..) = annotation.result_type {
+            imports
+                .entry("Data.Maybe")
+                .or_default()
+                .insert("listToMaybe");
+        }
+    }
+    if !structs.is_empty() {
+        let names = imports.entry(from_row).or_default();
+        names.insert("FromRow (..)");
+        names.insert("field");
+    }
+
+    writeln!(out, "{{-# LANGUAGE OverloadedStrings #-}}")?;
+    writeln!(out)?;
+    writeln!(out, "module Queries where")?;
+    writeln!(out)?;
+    for (module, names) in &imports {
+        let names: Vec<&str> = names.iter().cloned().collect();
+        writeln!(out, "import {} ({})", module, names.join(", "))?;
+    }
+
+    for (name, fields) in &structs {
+        // Record fields are functions in the module scope, so we prefix them
+        // with the type name to avoid clashes between records, and with e.g. `id`.
+        let prefix = match name.chars().next() {
+            Some(first) => format!("{}{}", first.to_lowercase(), &name[first.len_utf8()..]),
+            None => String::new(),
+        };
+        writeln!(out)?;
+        writeln!(out, "data {} = {}", name, name)?;
+        for (i, field) in fields.iter().enumerate() {
+            writeln!(
+                out,
+                "  {} {}{} :: {}",
+                if i == 0 { "{" } else { "," },
+                prefix,
+                to_camel_case(field.ident, true),
+                format_type(&field.type_)
+            )?;
+        }
+        writeln!(out, "  }}")?;
+        writeln!(out, "  deriving (Eq, Show)")?;
+        writeln!(out)?;
+        writeln!(out, "instance FromRow {} where", name)?;
+        let fields: Vec<&str> = fields.iter().map(|_| "field").collect();
+        writeln!(out, "  fromRow = {} <$> {}", name, fields.join(" <*> "))?;
+    }
+
+    for query in &queries {
+        write_function(query, out)?;
+    }
+
+    Ok(())
+}
+
+/// Return the type of the rows that the query returns.
+fn row_type<'a, 'b>(result_type: &'b Type<&'a str>) -> &'b Type<&'a str> {
+    match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => inner,
+        other => other,
+    }
+}
+
+/// Whether the row is a single column, which `FromRow` needs wrapped in `Only`.
+fn is_scalar(row_type: &Type<&str>) -> bool {
+    matches!(row_type, Type::Simple(..) | Type::Option(..))
+}
+
+/// Add the modules and names that need to be imported to write the type.
+fn collect_imports(type_: &Type<&str>, imports: &mut BTreeMap<&str, BTreeSet<&str>>) {
+    match type_ {
+        Type::Unit | Type::Struct(..) => {}
+        Type::Simple(t) => {
+            if let Some((_, haskell, Some(module))) = TYPES.iter().find(|(rust, ..)| rust == t) {
+                imports.entry(module).or_default().insert(haskell);
+            }
+        }
+        Type::Iterator(inner) | Type::Option(inner) => collect_imports(inner, imports),
+        Type::Tuple(ts) => {
+            for t in ts {
+                collect_imports(t, imports);
+            }
+        }
+    }
+}
+
+/// Format a type as a Haskell type.
+///
+/// Iterators become lists.
+fn format_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Unit => "()".to_string(),
+        Type::Simple(t) => match TYPES.iter().find(|(rust, ..)| rust == t) {
+            Some((_, haskell, _)) => haskell.to_string(),
+            None => t.to_string(),
+        },
+        Type::Iterator(t) => format!("[{}]", format_type(t)),
+        Type::Option(t) => format!("Maybe {}", format_type_arg(t)),
+        Type::Tuple(ts) if ts.len() == 1 => format!("Only {}", format_type_arg(&ts[0])),
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = ts.iter().map(format_type).collect();
+            format!("({})", elements.join(", "))
+        }
+        Type::Struct(name, _fields) => name.to_string(),
+    }
+}
+
+/// Format a type as an argument of a type constructor, parenthesized if needed.
+fn format_type_arg(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Option(..) => format!("({})", format_type(type_)),
+        Type::Tuple(ts) if ts.len() == 1 => format!("({})", format_type(type_)),
+        _ => format_type(type_),
+    }
+}
+
+/// Write the query as a multi-line string literal, using string gaps.
+fn write_string_literal(text: &str, indent: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    let lines: Vec<&str> = text.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        let escaped = line.replace('\\', "\\\\").replace('"', "\\\"");
+        let start = if i == 0 { "\"" } else { "\\" };
+        let end = if i + 1 < lines.len() { "\\n\\" } else { "\"" };
+        writeln!(out, "{}{}{}{}", indent, start, escaped, end)?;
+    }
+    Ok(())
+}
+
+fn write_function(query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    let annotation = &query.annotation;
+    let result_type = &annotation.result_type;
+    let name = to_camel_case(annotation.name, false);
+
+    writeln!(out)?;
+    for (i, doc_line) in query.docs.iter().enumerate() {
+        match i {
+            0 => writeln!(out, "-- |{}", doc_line)?,
+            _ => writeln!(out, "--{}", doc_line)?,
+        }
+    }
+    write!(out, "{} :: Connection", name)?;
+    for param in &annotation.parameters {
+        write!(out, " -> {}", format_type(&param.type_))?;
+    }
+    writeln!(out, " -> IO {}", format_type_arg(result_type))?;
+
+    write!(out, "{} conn", name)?;
+    for param in &annotation.parameters {
+        write!(out, " {}", to_camel_case(param.ident, false))?;
+    }
+    writeln!(out, " = do")?;
+
+    let (text, params) = query_text(query, Placeholder::Question);
+    let params: Vec<String> = params.iter().map(|p| to_camel_case(p, false)).collect();
+
+    let function = match (result_type, params.len()) {
+        (Type::Unit, 0) => "execute_",
+        (Type::Unit, _) => "execute",
+        (_, 0) => "query_",
+        (_, _) => "query",
+    };
+    match result_type {
+        Type::Unit => writeln!(out, "  _ <-")?,
+        _ => writeln!(out, "  rows <-")?,
+    }
+    writeln!(out, "    {}", function)?;
+    writeln!(out, "      conn")?;
+    write_string_literal(&text, "      ", out)?;
+    match params.len() {
+        0 => {}
+        1 => writeln!(out, "      (Only {})", params[0])?,
+        _ => writeln!(out, "      ({})", params.join(", "))?,
+    }
+
+    // Single columns come wrapped in `Only`, unwrap them.
+    let unwrap = if is_scalar(row_type(result_type)) {
+        "fromOnly "
+    } else {
+        ""
+    };
+    match result_type {
+        Type::Unit => writeln!(out, "  pure ()"),
+        Type::Option(..) if unwrap.is_empty() => writeln!(out, "  pure (listToMaybe rows)"),
+        Type::Option(..) => writeln!(out, "  pure (fromOnly <$> listToMaybe rows)"),
+        Type::Iterator(..) if unwrap.is_empty() => writeln!(out, "  pure rows"),
+        Type::Iterator(..) => writeln!(out, "  pure (map fromOnly rows)"),
+        _ => {
+            writeln!(out, "  case rows of")?;
+            writeln!(out, "    row : _ -> pure ({}row)", unwrap)?;
+            writeln!(
+                out,
+                "    [] -> fail \"Expected a row, but the query returned none.\""
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::dialect::Dialect;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str, dialect: Dialect) -> String {
+        generate_for_test(input, |input, doc, out| {
+            process_file(input, doc, dialect, out)
+        })
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: Option<String>"
+from users
+where name = :name;
+"#;
+        let expected = r#"{-# LANGUAGE OverloadedStrings #-}
+
+module Queries where
+
+import Data.Int (Int64)
+import Data.Maybe (listToMaybe)
+import Data.Text (Text)
+import Database.SQLite.Simple (Connection, Only (..), query)
+import Database.SQLite.Simple.FromRow (FromRow (..), field)
+
+data User = User
+  { userId :: Int64
+  , userName :: Text
+  , userEmail :: Maybe Text
+  }
+  deriving (Eq, Show)
+
+instance FromRow User where
+  fromRow = User <$> field <*> field <*> field
+
+-- | Look up a user by username.
+getUserByName :: Connection -> Text -> IO (Maybe User)
+getUserByName conn name = do
+  rows <-
+    query
+      conn
+      "select id as id, name as name, email as email\n\
+      \from users\n\
+      \where name = ?;"
+      (Only name)
+  pure (listToMaybe rows)
+"#;
+        assert_eq!(generate(input, Dialect::Sqlite), expected);
+    }
+
+    #[test]
+    fn it_passes_every_occurrence_of_a_parameter() {
+        let input = "
+        -- @query get_range(low: i64, high: i64) -> Iterator<(i64, String)>
+        select id as \"id: i64\", name as \"name: String\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input, Dialect::Postgres);
+        assert!(output.contains("import Database.PostgreSQL.Simple (Connection, query)\n"));
+        assert!(output.contains("getRange :: Connection -> Int64 -> Int64 -> IO [(Int64, Text)]\n"));
+        assert!(
+            output.contains("where id >= ? and id < ? and id <> ?;\"\n      (low, high, low)\n")
+        );
+        assert!(output.contains("  pure rows\n"));
+    }
+
+    #[test]
+    fn it_unwraps_single_columns() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input, Dialect::Sqlite);
+        assert!(output.contains(
+            "import Database.SQLite.Simple (Connection, Only (..), execute_, query, query_)\n"
+        ));
+        assert!(output.contains("setupSchema :: Connection -> IO ()\n"));
+        assert!(output.contains("  _ <-\n    execute_\n      conn\n"));
+        assert!(output.contains("    row : _ -> pure (fromOnly row)\n"));
+        assert!(output.contains("data NewUser = NewUser\n  { newUserId :: Int64\n"));
+    }
+}
@@ -0,0 +1,469 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type};
+use crate::target::{collect_structs, column_names, query_text, to_camel_case, Placeholder};
+use crate::Span;
+
+/// Java types for the simple types that can occur in annotations.
+///
+/// The elements are the annotation type, the Java type, the boxed Java type
+/// to use when the value can be null, and the suffix of the `ResultSet` getter
+/// and `PreparedStatement` setter. Simple types that are not in this table are
+/// used as-is, and read with `getObject`.
+///
+/// Java has no unsigned integers, so unsigned types map to the next wider
+/// signed type. There is none for `u64`, so it maps to `long`, and values
+/// larger than `Long.MAX_VALUE` come out negative.
+const TYPES: &[(&str, &str, &str, &str)] = &[
+    ("i8", "byte", "Byte", "Byte"),
+    ("i16", "short", "Short", "Short"),
+    ("i32", "int", "Integer", "Int"),
+    ("i64", "long", "Long", "Long"),
+    ("u8", "short", "Short", "Short"),
+    ("u16", "int", "Integer", "Int"),
+    ("u32", "long", "Long", "Long"),
+    ("u64", "long", "Long", "Long"),
+    ("f32", "float", "Float", "Float"),
+    ("f64", "double", "Double", "Double"),
+    ("bool", "boolean", "Boolean", "Boolean"),
+    ("&str", "String", "String", "String"),
+    ("String", "String", "String", "String"),
+    ("&[u8]", "byte[]", "byte[]", "Bytes"),
+];
+
+/// Generate a Java class that uses JDBC.
+///
+/// Named parameters are rewritten to positional `?`, and every occurrence is
+/// bound to the declared parameter with that name.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+
+    let queries: Vec<&Query<&str>> = doc
+        .sections
+        .iter()
+        .filter_map(|section| match section {
+            Section::Query(query) => Some(query),
+            Section::Verbatim(..) => None,
+        })
+        .collect();
+    let any_rows = queries
+        .iter()
+        .any(|q| !matches!(q.annotation.result_type, Type::Unit));
+    let any_list = queries
+        .iter()
+        .any(|q| matches!(q.annotation.result_type, Type::Iterator(..)));
+    let any_optional = queries
+        .iter()
+        .any(|q| matches!(q.annotation.result_type, Type::Option(..)));
+
+    writeln!(out, "import java.sql.Connection;")?;
+    writeln!(out, "import java.sql.PreparedStatement;")?;
+    if any_rows {
+        writeln!(out, "import java.sql.ResultSet;")?;
+    }
+    writeln!(out, "import java.sql.SQLException;")?;
+    if any_list {
+        writeln!(out, "import java.util.ArrayList;")?;
+        writeln!(out, "import java.util.List;")?;
+    }
+    if any_optional {
+        writeln!(out, "import java.util.Optional;")?;
+    }
+    writeln!(out)?;
+    writeln!(out, "public final class Queries {{")?;
+    writeln!(out, "    private Queries() {{}}")?;
+
+    for (name, fields) in collect_structs(&doc) {
+        let components: Vec<(String, &Type<&str>)> = fields
+            .iter()
+            .map(|field| (to_camel_case(field.ident, false), &field.type_))
+            .collect();
+        write_record(name, &components, out)?;
+    }
+
+    for query in &queries {
+        write_query(query, out)?;
+    }
+
+    writeln!(out, "}}")
+}
+
+/// Format a type as a Java type.
+///
+/// Nullable values become boxed types, wrappers of results are handled by
+/// the caller.
+fn format_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Simple(t) => match TYPES.iter().find(|(rust, ..)| rust == t) {
+            Some((_, java, _, _)) => java.to_string(),
+            None => t.to_string(),
+        },
+        Type::Option(inner) => format_boxed_type(inner),
+        Type::Struct(name, _fields) => name.to_string(),
+        // Tuples get a record in place of the tuple, and units and iterators
+        // cannot occur other than as the result type.
+        _ => "Object".to_string(),
+    }
+}
+
+/// Format a type as a Java type that can be used as a type argument.
+fn format_boxed_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Simple(t) => match TYPES.iter().find(|(rust, ..)| rust == t) {
+            Some((_, _, boxed, _)) => boxed.to_string(),
+            None => t.to_string(),
+        },
+        other => format_type(other),
+    }
+}
+
+/// Format the expression that reads the column at the 1-based `index`.
+fn read_column(type_: &Type<&str>, index: usize) -> String {
+    match type_ {
+        Type::Simple(t) => match TYPES.iter().find(|(rust, ..)| rust == t) {
+            Some((_, _, _, accessor)) => format!("rs.get{}({})", accessor, index),
+            None => format!("rs.getObject({}, {}.class)", index, t),
+        },
+        // The primitive getters return 0 or false for null, so for nullable
+        // values we go through `getObject`, except for types that are not
+        // primitive in the first place.
+        Type::Option(inner) => match &**inner {
+            Type::Simple(t) if *t == "&str" || *t == "String" || *t == "&[u8]" => {
+                read_column(inner, index)
+            }
+            other => format!(
+                "rs.getObject({}, {}.class)",
+                index,
+                format_boxed_type(other)
+            ),
+        },
+        other => format!("rs.getObject({}, {}.class)", index, format_type(other)),
+    }
+}
+
+/// Format the statement that binds the parameter at the 1-based `index`.
+fn bind_param(type_: &Type<&str>, index: usize, name: &str) -> String {
+    match type_ {
+        Type::Simple(t) => match TYPES.iter().find(|(rust, ..)| rust == t) {
+            Some((_, _, _, accessor)) => format!("statement.set{}({}, {});", accessor, index, name),
+            None => format!("statement.setObject({}, {});", index, name),
+        },
+        _ => format!("statement.setObject({}, {});", index, name),
+    }
+}
+
+fn write_record(
+    name: &str,
+    components: &[(String, &Type<&str>)],
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let components: Vec<String> = components
+        .iter()
+        .map(|(name, type_)| format!("{} {}", format_type(type_), name))
+        .collect();
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    public record {}({}) {{}}",
+        name,
+        components.join(", ")
+    )
+}
+
+fn write_doc_comment(docs: &[&str], out: &mut dyn io::Write) -> io::Result<()> {
+    if docs.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "    /**")?;
+    for line in docs {
+        // Do not let the documentation end the comment early.
+        let line = line.replace("*/", "*&#47;");
+        if line.trim().is_empty() {
+            writeln!(out, "     *")?;
+        } else {
+            writeln!(out, "     *{}", line)?;
+        }
+    }
+    writeln!(out, "     */")
+}
+
+/// Write the query as a text block, indented by `indent`.
+fn write_text_block(text: &str, indent: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    let escaped = text.replace('\\', "\\\\").replace("\"\"\"", "\\\"\"\"");
+    writeln!(out, "\"\"\"")?;
+    for line in escaped.lines() {
+        if line.trim().is_empty() {
+            writeln!(out)?;
+        } else {
+            writeln!(out, "{}{}", indent, line)?;
+        }
+    }
+    write!(out, "{}\"\"\"", indent)
+}
+
+fn write_query(query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    let annotation = &query.annotation;
+    let result_type = &annotation.result_type;
+    let method_name = to_camel_case(annotation.name, false);
+
+    let row_type = match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => &**inner,
+        other => other,
+    };
+
+    // Java has no tuples, so we name the typed columns in a record instead.
+    let (row_type_name, new_row) = match row_type {
+        Type::Unit => (String::new(), String::new()),
+        Type::Struct(name, fields) => {
+            let args: Vec<String> = fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| read_column(&field.type_, i + 1))
+                .collect();
+            (
+                name.to_string(),
+                format!("new {}({})", name, args.join(", ")),
+            )
+        }
+        Type::Tuple(ts) => {
+            let columns = column_names(query);
+            let components: Vec<(String, &Type<&str>)> = ts
+                .iter()
+                .enumerate()
+                .map(|(i, t)| match columns.get(i) {
+                    Some(column) => (to_camel_case(column, false), t),
+                    None => (format!("column{}", i + 1), t),
+                })
+                .collect();
+            let name = format!("{}Row", to_camel_case(annotation.name, true));
+            write_record(&name, &components, out)?;
+            let args: Vec<String> = ts
+                .iter()
+                .enumerate()
+                .map(|(i, t)| read_column(t, i + 1))
+                .collect();
+            let new_row = format!("new {}({})", name, args.join(", "));
+            (name, new_row)
+        }
+        other => (format_type(other), read_column(other, 1)),
+    };
+
+    // Type arguments cannot be primitive types.
+    let boxed_row_type_name = match row_type {
+        Type::Simple(..) | Type::Option(..) => format_boxed_type(row_type),
+        _ => row_type_name.clone(),
+    };
+    let return_type = match result_type {
+        Type::Unit => "void".to_string(),
+        Type::Option(..) => format!("Optional<{}>", boxed_row_type_name),
+        Type::Iterator(..) => format!("List<{}>", boxed_row_type_name),
+        _ => row_type_name,
+    };
+
+    writeln!(out)?;
+    write_doc_comment(&query.docs, out)?;
+    write!(
+        out,
+        "    public static {} {}(Connection connection",
+        return_type, method_name
+    )?;
+    for param in &annotation.parameters {
+        write!(
+            out,
+            ", {} {}",
+            format_type(&param.type_),
+            to_camel_case(param.ident, false)
+        )?;
+    }
+    writeln!(out, ") throws SQLException {{")?;
+
+    let (text, params) = query_text(query, Placeholder::Question);
+    write!(out, "        String sql = ")?;
+    write_text_block(&text, "            ", out)?;
+    writeln!(out, ";")?;
+    writeln!(
+        out,
+        "        try (PreparedStatement statement = connection.prepareStatement(sql)) {{"
+    )?;
+
+    // Every `?` is a separate parameter, so a parameter that occurs more than
+    // once is bound once per occurrence.
+    for (i, name) in params.iter().enumerate() {
+        let declared = annotation
+            .parameters
+            .iter()
+            .find(|param| param.ident == *name);
+        let type_ = match declared {
+            Some(param) => &param.type_,
+            // Undeclared parameters are an error that we report before
+            // generating code, but we can bind them regardless.
+            None => &Type::Unit,
+        };
+        writeln!(
+            out,
+            "            {}",
+            bind_param(type_, i + 1, &to_camel_case(name, false))
+        )?;
+    }
+
+    match result_type {
+        Type::Unit => {
+            writeln!(out, "            statement.execute();")?;
+        }
+        _ => {
+            writeln!(
+                out,
+                "            try (ResultSet rs = statement.executeQuery()) {{"
+            )?;
+            match result_type {
+                Type::Option(..) => {
+                    writeln!(out, "                if (!rs.next()) {{")?;
+                    writeln!(out, "                    return Optional.empty();")?;
+                    writeln!(out, "                }}")?;
+                    writeln!(out, "                return Optional.of({});", new_row)?;
+                }
+                Type::Iterator(..) => {
+                    writeln!(
+                        out,
+                        "                {} result = new ArrayList<>();",
+                        return_type
+                    )?;
+                    writeln!(out, "                while (rs.next()) {{")?;
+                    writeln!(out, "                    result.add({});", new_row)?;
+                    writeln!(out, "                }}")?;
+                    writeln!(out, "                return result;")?;
+                }
+                _ => {
+                    writeln!(out, "                if (!rs.next()) {{")?;
+                    writeln!(
+                        out,
+                        "                    throw new SQLException(\"Expected a row, but the query returned none.\");"
+                    )?;
+                    writeln!(out, "                }}")?;
+                    writeln!(out, "                return {};", new_row)?;
+                }
+            }
+            writeln!(out, "            }}")?;
+        }
+    }
+
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: Option<String>"
+from users
+where name = :name;
+"#;
+        let expected = r#"import java.sql.Connection;
+import java.sql.PreparedStatement;
+import java.sql.ResultSet;
+import java.sql.SQLException;
+import java.util.Optional;
+
+public final class Queries {
+    private Queries() {}
+
+    public record User(long id, String name, String email) {}
+
+    /**
+     * Look up a user by username.
+     */
+    public static Optional<User> getUserByName(Connection connection, String name) throws SQLException {
+        String sql = """
+            select id as id, name as name, email as email
+            from users
+            where name = ?;
+            """;
+        try (PreparedStatement statement = connection.prepareStatement(sql)) {
+            statement.setString(1, name);
+            try (ResultSet rs = statement.executeQuery()) {
+                if (!rs.next()) {
+                    return Optional.empty();
+                }
+                return Optional.of(new User(rs.getLong(1), rs.getString(2), rs.getString(3)));
+            }
+        }
+    }
+}
+"#;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_binds_every_occurrence_of_a_parameter() {
+        let input = "
+        -- @query get_range(low: i64, high: Option<i64>) -> Iterator<(i64, Option<f64>)>
+        select id as \"id: i64\", score as \"score: Option<f64>\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input);
+        assert!(output.contains("import java.util.List;\n"));
+        assert!(!output.contains("import java.util.Optional;\n"));
+        assert!(output.contains("    public record GetRangeRow(long id, Double score) {}\n"));
+        assert!(output.contains(
+            "    public static List<GetRangeRow> getRange(Connection connection, long low, Long high)"
+        ));
+        assert!(output.contains("where id >= ? and id < ? and id <> ?;"));
+        assert!(output.contains(
+            "            statement.setLong(1, low);\n            statement.setObject(2, high);\n            statement.setLong(3, low);\n"
+        ));
+        assert!(output.contains(
+            "                    result.add(new GetRangeRow(rs.getLong(1), rs.getObject(2, Double.class)));\n"
+        ));
+    }
+
+    #[test]
+    fn it_widens_unsigned_integers() {
+        let input = "
+        -- @query get_counts(limit: u64) -> Iterator<(u8, u16, u32, Option<u64>)>
+        select a, b, c, d from t limit :limit;
+        ";
+        let output = generate(input);
+        assert!(output.contains(
+            "    public record GetCountsRow(short column1, int column2, long column3, Long column4) {}\n"
+        ));
+        assert!(output.contains("getCounts(Connection connection, long limit)"));
+        assert!(output.contains("            statement.setLong(1, limit);\n"));
+        assert!(output.contains(
+            "new GetCountsRow(rs.getShort(1), rs.getInt(2), rs.getLong(3), rs.getObject(4, Long.class))"
+        ));
+        assert!(!output.contains("u64"));
+    }
+
+    #[test]
+    fn it_executes_unit_queries_and_reads_scalars() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input);
+        assert!(output.contains(
+            "    public static void setupSchema(Connection connection) throws SQLException {\n"
+        ));
+        assert!(output.contains("            statement.execute();\n"));
+        assert!(output.contains("                return rs.getLong(1);\n"));
+        assert!(output.contains("    public static List<User> listAllUsers("));
+    }
+}
@@ -0,0 +1,234 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Fragment, Query, Section, TypedIdent};
+use crate::json::{write_fragment_fields, write_string, write_type, PROTOCOL_VERSION};
+use crate::Span;
+
+/// Maps byte offsets to line and column numbers.
+struct LineIndex {
+    /// Byte offset of the start of every line.
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(input: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(input.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { line_starts }
+    }
+
+    /// Return the 1-based line and 1-based byte column of the offset.
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        (line + 1, offset - self.line_starts[line] + 1)
+    }
+}
+
+struct Writer<'a> {
+    input: &'a str,
+    lines: LineIndex,
+    out: &'a mut dyn io::Write,
+}
+
+impl<'a> Writer<'a> {
+    fn write_location(&mut self, offset: usize) -> io::Result<()> {
+        let (line, column) = self.lines.locate(offset);
+        write!(
+            self.out,
+            r#"{{"offset":{},"line":{},"column":{}}}"#,
+            offset, line, column
+        )
+    }
+
+    fn write_span(&mut self, span: Span) -> io::Result<()> {
+        write!(self.out, r#"{{"start":"#)?;
+        self.write_location(span.start)?;
+        write!(self.out, r#","end":"#)?;
+        self.write_location(span.end)?;
+        write!(self.out, "}}")
+    }
+
+    fn write_parameter(&mut self, param: &TypedIdent<Span>) -> io::Result<()> {
+        let span = Span {
+            start: param.ident.start,
+            end: param.type_.span().map(|s| s.end).unwrap_or(param.ident.end),
+        };
+        write!(self.out, r#"{{"ident":"#)?;
+        write_string(param.ident.resolve(self.input), self.out)?;
+        write!(self.out, r#","type":"#)?;
+        write_type(&param.type_.resolve(self.input), self.out)?;
+        write!(self.out, r#","span":"#)?;
+        self.write_span(span)?;
+        write!(self.out, "}}")
+    }
+
+    fn write_query(&mut self, query: &Query<Span>) -> io::Result<()> {
+        let annotation = &query.annotation;
+
+        write!(self.out, r#"{{"name":"#)?;
+        write_string(annotation.name.resolve(self.input), self.out)?;
+        write!(self.out, r#","name_span":"#)?;
+        self.write_span(annotation.name)?;
+
+        // The span of the query is the span of its SQL statement.
+        if let (Some(first), Some(last)) = (query.fragments.first(), query.fragments.last()) {
+            write!(self.out, r#","span":"#)?;
+            self.write_span(Span {
+                start: fragment_span(first).start,
+                end: fragment_span(last).end,
+            })?;
+        }
+
+        write!(self.out, r#","docs":["#)?;
+        for (i, doc) in query.docs.iter().enumerate() {
+            if i > 0 {
+                write!(self.out, ",")?;
+            }
+            write!(self.out, r#"{{"text":"#)?;
+            write_string(doc.resolve(self.input), self.out)?;
+            write!(self.out, r#","span":"#)?;
+            self.write_span(*doc)?;
+            write!(self.out, "}}")?;
+        }
+
+        write!(self.out, r#"],"parameters":["#)?;
+        for (i, param) in annotation.parameters.iter().enumerate() {
+            if i > 0 {
+                write!(self.out, ",")?;
+            }
+            self.write_parameter(param)?;
+        }
+
+        write!(self.out, r#"],"result_type":"#)?;
+        write_type(&annotation.result_type.resolve(self.input), self.out)?;
+
+        write!(self.out, r#","fragments":["#)?;
+        let resolved = query.resolve(self.input);
+        let fragments = query.fragments.iter().zip(&resolved.fragments);
+        for (i, (fragment, resolved_fragment)) in fragments.enumerate() {
+            if i > 0 {
+                write!(self.out, ",")?;
+            }
+            write!(self.out, "{{")?;
+            write_fragment_fields(&resolved, resolved_fragment, self.out)?;
+            write!(self.out, r#","span":"#)?;
+            self.write_span(fragment_span(fragment))?;
+            write!(self.out, "}}")?;
+        }
+
+        write!(self.out, "]}}")
+    }
+}
+
+fn fragment_span(fragment: &Fragment<Span>) -> Span {
+    match fragment {
+        Fragment::Verbatim(span) => *span,
+        Fragment::TypedIdent(span, _) => *span,
+        Fragment::Param(span, _kind) => *span,
+    }
+}
+
+/// Describe every query in the document as JSON, on a single line.
+///
+/// Unlike the plugin request, this includes source locations, and it omits the
+/// verbatim sections between queries. Types and fragments have the same shape
+/// as in the plugin protocol, see `plugin-protocol.schema.json`.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let mut writer = Writer {
+        input,
+        lines: LineIndex::new(input),
+        out,
+    };
+    write!(
+        writer.out,
+        r#"{{"version":{},"queries":["#,
+        PROTOCOL_VERSION
+    )?;
+    let queries = parsed.sections.iter().filter_map(|section| match section {
+        Section::Query(query) => Some(query),
+        Section::Verbatim(..) => None,
+    });
+    for (i, query) in queries.enumerate() {
+        if i > 0 {
+            write!(writer.out, ",")?;
+        }
+        writer.write_query(query)?;
+    }
+    writeln!(writer.out, "]}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{process_file, LineIndex};
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+
+    #[test]
+    fn line_index_locates_offsets() {
+        let lines = LineIndex::new("ab\ncd\n\nef");
+        assert_eq!(lines.locate(0), (1, 1));
+        assert_eq!(lines.locate(2), (1, 3));
+        assert_eq!(lines.locate(3), (2, 1));
+        assert_eq!(lines.locate(6), (3, 1));
+        assert_eq!(lines.locate(8), (4, 2));
+    }
+
+    #[test]
+    fn it_describes_queries_with_locations() {
+        let input = "create table t (x);\n\n-- Doc.\n-- @query q(id: i64) -> i64\nselect :id;\n";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        let doc = parser.parse_document().unwrap();
+        let mut out = Vec::new();
+        process_file(input, &doc, &mut out).unwrap();
+        let loc = |offset: usize, line: usize, column: usize| {
+            format!(
+                r#"{{"offset":{},"line":{},"column":{}}}"#,
+                offset, line, column
+            )
+        };
+        let expected = format!(
+            concat!(
+                r#"{{"version":2,"queries":[{{"name":"q","name_span":{{"start":{},"end":{}}},"#,
+                r#""span":{{"start":{},"end":{}}},"#,
+                r#""docs":[{{"text":" Doc.","span":{{"start":{},"end":{}}}}}],"#,
+                r#""parameters":[{{"ident":"id","type":{{"kind":"simple","name":"i64"}},"span":{{"start":{},"end":{}}}}}],"#,
+                r#""result_type":{{"kind":"simple","name":"i64"}},"#,
+                r#""fragments":[{{"kind":"verbatim","text":"select ","span":{{"start":{},"end":{}}}}},"#,
+                r#"{{"kind":"param","text":":id","name":"id","style":"colon","#,
+                r#""span":{{"start":{},"end":{}}}}},"#,
+                r#"{{"kind":"verbatim","text":";","span":{{"start":{},"end":{}}}}}]}}]}}"#,
+                "\n",
+            ),
+            loc(39, 4, 11),
+            loc(40, 4, 12),
+            loc(57, 5, 1),
+            loc(68, 5, 12),
+            loc(23, 3, 3),
+            loc(28, 3, 8),
+            loc(41, 4, 13),
+            loc(48, 4, 20),
+            loc(57, 5, 1),
+            loc(64, 5, 8),
+            loc(64, 5, 8),
+            loc(67, 5, 11),
+            loc(67, 5, 11),
+            loc(68, 5, 12),
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
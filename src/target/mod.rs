@@ -0,0 +1,541 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+mod c_sqlite3;
+mod debug;
+mod go;
+mod haskell;
+mod jdbc;
+mod json;
+pub mod plugin;
+mod python_sqlite3;
+mod rust;
+mod rust_postgres;
+mod rust_rusqlite;
+mod rust_sqlite;
+mod rust_sqlx;
+mod rust_tokio_postgres;
+mod swift;
+pub mod template;
+mod typescript;
+
+use std::io;
+
+use crate::ast::{Document, Fragment, ParamKind, Query, Section, Type, TypedIdent};
+use crate::dialect::Dialect;
+use crate::Span;
+
+/// Settings that some targets take into account.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The database that the queries are written for.
+    pub dialect: Dialect,
+
+    /// Target types to use for simple annotation types, e.g. `("i64", "bigint")`.
+    ///
+    /// These take precedence over the built-in mapping of the target.
+    pub type_overrides: Vec<(String, String)>,
+}
+
+impl Options {
+    /// Return the overridden target type for a simple annotation type, if any.
+    pub fn type_override(&self, type_: &str) -> Option<&str> {
+        self.type_overrides
+            .iter()
+            .find(|(from, _)| from == type_)
+            .map(|(_, to)| &to[..])
+    }
+}
+
+/// A code generator for a particular language and library.
+///
+/// Backends are looked up by name in a [`Registry`]. To add a target without
+/// forking this crate, implement this trait, register it, and run the CLI with
+/// [`crate::cli::run`].
+pub trait Backend {
+    /// The name that selects this backend with `--target`, e.g. `rust-sqlite`.
+    fn name(&self) -> &str;
+
+    /// A one-line description, for the `--target=help` listing.
+    fn description(&self) -> &str;
+
+    /// Whether the backend applies [`Options::type_overrides`].
+    ///
+    /// The CLI rejects `--map-type` for backends that would ignore it.
+    fn supports_type_overrides(&self) -> bool {
+        false
+    }
+
+    /// Generate code for a document that has been parsed and checked.
+    ///
+    /// The spans in `doc` refer to `input`.
+    fn generate(
+        &self,
+        input: &str,
+        doc: &Document<Span>,
+        options: &Options,
+        out: &mut dyn io::Write,
+    ) -> io::Result<()>;
+}
+
+type GenerateFn = fn(&str, &Document<Span>, &Options, &mut dyn io::Write) -> io::Result<()>;
+
+/// A backend that is part of this crate.
+#[derive(Copy, Clone)]
+struct Builtin {
+    name: &'static str,
+    description: &'static str,
+    supports_type_overrides: bool,
+    generate: GenerateFn,
+}
+
+impl Backend for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn supports_type_overrides(&self) -> bool {
+        self.supports_type_overrides
+    }
+
+    fn generate(
+        &self,
+        input: &str,
+        doc: &Document<Span>,
+        options: &Options,
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        (self.generate)(input, doc, options, out)
+    }
+}
+
+/// The backends that are part of this crate, in the order that `--target=help` lists them.
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "debug",
+        description: "For debugging, run the parser and print a highlighted document.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| debug::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "rust-sqlite",
+        description: "Rust code for the `sqlite` crate.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| rust_sqlite::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "rust-rusqlite",
+        description: "Rust code for the `rusqlite` crate.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| rust_rusqlite::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "rust-postgres",
+        description: "Rust code for the synchronous `postgres` crate.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| rust_postgres::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "rust-tokio-postgres",
+        description: "Rust code for the asynchronous `tokio-postgres` crate.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| rust_tokio_postgres::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "rust-sqlx",
+        description: "Rust code for the `sqlx` crate, without compile-time checked queries.",
+        supports_type_overrides: false,
+        generate: |input, doc, options, out| {
+            rust_sqlx::process_file(input, doc, options.dialect, out)
+        },
+    },
+    Builtin {
+        name: "python-sqlite3",
+        description: "Python code for the `sqlite3` module, with dataclasses for structs.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| python_sqlite3::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "go",
+        description: "Go code for the `database/sql` package.",
+        supports_type_overrides: false,
+        generate: |input, doc, options, out| go::process_file(input, doc, options.dialect, out),
+    },
+    Builtin {
+        name: "c-sqlite3",
+        description: "A C header-only library for the sqlite3 C API.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| c_sqlite3::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "haskell",
+        description: "A Haskell module for `sqlite-simple`, or `postgresql-simple` with the postgres dialect.",
+        supports_type_overrides: false,
+        generate: |input, doc, options, out| {
+            haskell::process_file(input, doc, options.dialect, out)
+        },
+    },
+    Builtin {
+        name: "jdbc",
+        description: "Java code that uses JDBC, with records for structs.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| jdbc::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "typescript",
+        description: "TypeScript code for `better-sqlite3`, or `pg` with the postgres dialect.",
+        supports_type_overrides: true,
+        generate: |input, doc, options, out| typescript::process_file(input, doc, options, out),
+    },
+    Builtin {
+        name: "swift",
+        description: "Swift code for the sqlite3 C API, with `Decodable` structs.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| swift::process_file(input, doc, out),
+    },
+    Builtin {
+        name: "json",
+        description: "A JSON description of every query with source locations, for tooling.",
+        supports_type_overrides: false,
+        generate: |input, doc, _, out| json::process_file(input, doc, out),
+    },
+];
+
+/// The backends that the CLI can select with `--target`.
+#[derive(Default)]
+pub struct Registry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Registry {
+    /// Create a registry without any backends.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with all backends that are part of this crate.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        for builtin in BUILTINS {
+            registry.register(Box::new(*builtin));
+        }
+        registry
+    }
+
+    /// Add a backend.
+    ///
+    /// If a backend with the same name was registered before, it is replaced,
+    /// but it keeps its place in the listing.
+    pub fn register(&mut self, backend: Box<dyn Backend>) {
+        match self
+            .backends
+            .iter_mut()
+            .find(|b| b.name() == backend.name())
+        {
+            Some(existing) => *existing = backend,
+            None => self.backends.push(backend),
+        }
+    }
+
+    /// Return the backend with the given name, if there is one.
+    pub fn get(&self, name: &str) -> Option<&dyn Backend> {
+        self.backends
+            .iter()
+            .find(|b| b.name() == name)
+            .map(|b| b.as_ref())
+    }
+
+    /// Iterate the backends in order of registration.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Backend> {
+        self.backends.iter().map(|b| b.as_ref())
+    }
+}
+
+/// Build the error for a target that cannot generate code for a dialect.
+pub fn unsupported_dialect(target: &str, dialect: Dialect) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "The {} target does not support the {} dialect.",
+            target,
+            dialect.name()
+        ),
+    )
+}
+
+/// Return the struct inside a result type, if there is one.
+fn get_struct<'a, 'b>(type_: &'b Type<&'a str>) -> Option<(&'a str, &'b [TypedIdent<&'a str>])> {
+    match type_ {
+        Type::Option(inner) | Type::Iterator(inner) => get_struct(inner),
+        Type::Struct(name, fields) => Some((name, fields)),
+        _ => None,
+    }
+}
+
+/// Return the structs that queries in the document return, once per name.
+///
+/// When multiple queries return the same struct, the first occurrence is
+/// returned. All occurrences have the same fields, this is checked elsewhere.
+pub fn collect_structs<'a, 'b>(
+    doc: &'b Document<&'a str>,
+) -> Vec<(&'a str, &'b [TypedIdent<&'a str>])> {
+    let mut result: Vec<(&'a str, &'b [TypedIdent<&'a str>])> = Vec::new();
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            if let Some((name, fields)) = get_struct(&query.annotation.result_type) {
+                if result.iter().all(|(existing, _)| *existing != name) {
+                    result.push((name, fields));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Return the names of the typed columns of the query, in order.
+///
+/// Targets without tuples use these to name the elements of tuple results.
+pub fn column_names<'a>(query: &Query<&'a str>) -> Vec<&'a str> {
+    query
+        .fragments
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::TypedIdent(_, typed_ident) => Some(typed_ident.ident),
+            _ => None,
+        })
+        .collect()
+}
+
+/// How to write query parameters in the generated query.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Placeholder {
+    /// Write parameters by name, e.g. `:name`.
+    Named,
+
+    /// Number the parameters, e.g. `$1`, as Postgres requires.
+    Dollar,
+
+    /// Number the parameters with a question mark, e.g. `?1`, which SQLite accepts.
+    QuestionNumbered,
+
+    /// Replace every parameter with a bare `?`, bound by position.
+    Question,
+}
+
+/// Return the name of the declared parameter that a parameter binds to.
+///
+/// Named parameters bind by name, positional parameters by position. The
+/// parser rejects positions that the annotation does not declare.
+pub fn param_name<'a>(query: &Query<&'a str>, text: &'a str, kind: ParamKind) -> &'a str {
+    match kind.position() {
+        // Cut off the leading ':' or '@' to get the name.
+        None => &text[1..],
+        Some(n) => {
+            let param = n
+                .checked_sub(1)
+                .and_then(|i| query.annotation.parameters.get(i))
+                .expect("The parser only accepts positions of declared parameters.");
+            param.ident
+        }
+    }
+}
+
+/// Reconstruct the query, with typed identifiers replaced by the bare identifier.
+///
+/// Parameters are written in the style of `placeholder`, regardless of how
+/// they are written in the input. Returns the query, and the names of the
+/// declared parameters that they bind to, in the order in which they need to
+/// be bound. A parameter that occurs more than once reuses the
+/// index of its first occurrence. This is also how SQLite numbers named
+/// parameters, so the order applies to `Placeholder::Named` too. Bare `?`
+/// placeholders cannot refer back to an earlier index, so for
+/// `Placeholder::Question`, every occurrence needs to be bound.
+pub fn query_text<'a>(query: &Query<&'a str>, placeholder: Placeholder) -> (String, Vec<&'a str>) {
+    let mut result = String::new();
+    let mut params: Vec<&'a str> = Vec::new();
+
+    for fragment in &query.fragments {
+        match fragment {
+            Fragment::Verbatim(s) => result.push_str(s),
+            Fragment::TypedIdent(_, typed_ident) => result.push_str(typed_ident.ident),
+            Fragment::Param(s, kind) => {
+                let name = param_name(query, s, *kind);
+                let existing = match placeholder {
+                    Placeholder::Question => None,
+                    _ => params.iter().position(|p| *p == name),
+                };
+                let index = match existing {
+                    Some(i) => i,
+                    None => {
+                        params.push(name);
+                        params.len() - 1
+                    }
+                };
+                match placeholder {
+                    Placeholder::Named => {
+                        result.push(':');
+                        result.push_str(name);
+                    }
+                    Placeholder::Dollar => result.push_str(&format!("${}", index + 1)),
+                    Placeholder::QuestionNumbered => result.push_str(&format!("?{}", index + 1)),
+                    Placeholder::Question => result.push('?'),
+                }
+            }
+        }
+    }
+
+    (result, params)
+}
+
+/// Convert a snake_case name into camelCase, or PascalCase if `upper`.
+///
+/// Leading underscores are dropped.
+pub fn to_camel_case(name: &str, upper: bool) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut capitalize_next = upper;
+    for ch in name.chars() {
+        if ch == '_' {
+            capitalize_next = !result.is_empty() || upper;
+        } else if capitalize_next {
+            result.extend(ch.to_uppercase());
+            capitalize_next = false;
+        } else {
+            result.push(ch);
+        }
+    }
+    result
+}
+
+/// Parse the input and generate code for it with `process_file`, for tests.
+#[cfg(test)]
+fn generate_for_test<F>(input: &str, process_file: F) -> String
+where
+    F: FnOnce(&str, &Document<Span>, &mut dyn io::Write) -> io::Result<()>,
+{
+    use crate::analysis::structs::resolve_structs;
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+
+    let tokens = Lexer::new(input).run().expect("Failed to lex the input.");
+    let mut parser = Parser::new(input, &tokens);
+    let mut doc = parser.parse_document().expect("Failed to parse the input.");
+    resolve_structs(input, &mut doc);
+    let mut out = Vec::new();
+    process_file(input, &doc, &mut out).expect("Failed to write output.");
+    String::from_utf8(out).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{query_text, to_camel_case, Backend, Options, Placeholder, Registry};
+    use crate::ast::Document;
+    use crate::Span;
+    use std::io;
+
+    struct Custom(&'static str);
+
+    impl Backend for Custom {
+        fn name(&self) -> &str {
+            "debug"
+        }
+
+        fn description(&self) -> &str {
+            self.0
+        }
+
+        fn generate(
+            &self,
+            _input: &str,
+            _doc: &Document<Span>,
+            _options: &Options,
+            out: &mut dyn io::Write,
+        ) -> io::Result<()> {
+            write!(out, "{}", self.0)
+        }
+    }
+
+    #[test]
+    fn registry_lists_builtins_with_unique_names() {
+        let registry = Registry::with_builtins();
+        let names: Vec<&str> = registry.iter().map(|b| b.name()).collect();
+        assert_eq!(names[0], "debug");
+        assert!(names.contains(&"rust-sqlite"));
+        for (i, name) in names.iter().enumerate() {
+            assert!(
+                !names[i + 1..].contains(name),
+                "Duplicate backend {}.",
+                name
+            );
+        }
+        assert!(registry.get("help").is_none());
+        assert!(Registry::new().get("debug").is_none());
+    }
+
+    #[test]
+    fn backends_do_not_support_type_overrides_by_default() {
+        let registry = Registry::with_builtins();
+        assert!(registry
+            .get("typescript")
+            .unwrap()
+            .supports_type_overrides());
+        assert!(!registry
+            .get("rust-sqlite")
+            .unwrap()
+            .supports_type_overrides());
+        assert!(!Custom("Custom backend.").supports_type_overrides());
+    }
+
+    #[test]
+    fn registry_register_replaces_by_name() {
+        let mut registry = Registry::with_builtins();
+        let n = registry.iter().count();
+        registry.register(Box::new(Custom("Custom backend.")));
+        assert_eq!(registry.iter().count(), n);
+        assert_eq!(
+            registry.iter().next().unwrap().description(),
+            "Custom backend."
+        );
+        assert_eq!(
+            registry.get("debug").unwrap().description(),
+            "Custom backend."
+        );
+    }
+
+    #[test]
+    fn query_text_binds_positional_parameters_to_declared_names() {
+        use crate::lexer::sql::Lexer;
+        use crate::parser::document::Parser;
+
+        let input = "-- @query q(a: i64, b: i64)\nselect ?2, ?1, ?2;";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        let query = match parser.parse_section().unwrap().resolve(input) {
+            crate::ast::Section::Query(query) => query,
+            _ => panic!("Expected a query section."),
+        };
+        assert_eq!(
+            query_text(&query, Placeholder::Named),
+            ("select :b, :a, :b;".to_string(), vec!["b", "a"]),
+        );
+        assert_eq!(
+            query_text(&query, Placeholder::Question),
+            ("select ?, ?, ?;".to_string(), vec!["b", "a", "b"]),
+        );
+    }
+
+    #[test]
+    fn to_camel_case_capitalizes_words() {
+        assert_eq!(to_camel_case("get_user_by_name", true), "GetUserByName");
+        assert_eq!(to_camel_case("get_user_by_name", false), "getUserByName");
+        assert_eq!(to_camel_case("id", true), "Id");
+        assert_eq!(to_camel_case("_id", false), "id");
+    }
+}
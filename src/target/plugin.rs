@@ -0,0 +1,136 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::ast::Document;
+use crate::target::{Backend, Options};
+use crate::Span;
+
+/// A backend that runs an external program to generate code.
+///
+/// The program receives the document as JSON on stdin, as described in
+/// [`crate::json`], and its stdout becomes the output. Its stderr is passed
+/// through, and a nonzero exit status is an error. There is no way for the
+/// program to return multiple files, stdout is the only output.
+pub struct Plugin {
+    program: PathBuf,
+    name: String,
+}
+
+impl Plugin {
+    pub fn new(program: PathBuf) -> Self {
+        let name = program.to_string_lossy().into_owned();
+        Plugin { program, name }
+    }
+}
+
+impl Backend for Plugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        "An external code generator."
+    }
+
+    fn supports_type_overrides(&self) -> bool {
+        true
+    }
+
+    fn generate(
+        &self,
+        input: &str,
+        doc: &Document<Span>,
+        options: &Options,
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        let mut request = Vec::new();
+        crate::json::write_request(&doc.resolve(input), options, &mut request)?;
+
+        let mut child = Command::new(&self.program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Failed to start plugin '{}': {}", self.name, err),
+                )
+            })?;
+
+        // Write the request from a different thread, so a plugin that writes
+        // output before it has read all of its input cannot deadlock us.
+        let mut stdin = child.stdin.take().expect("We requested a pipe for stdin.");
+        let writer = thread::spawn(move || stdin.write_all(&request));
+        let output = child.wait_with_output()?;
+
+        // A plugin may exit without reading all of its input, that is not an
+        // error in itself, its exit status tells whether it succeeded.
+        match writer.join().expect("Failed to join the writer thread.") {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+            _ => {}
+        }
+
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Plugin '{}' failed, {}.", self.name, output.status),
+            ));
+        }
+
+        out.write_all(&output.stdout)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::Plugin;
+    use crate::dialect::Dialect;
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+    use crate::target::{Backend, Options};
+
+    fn generate(program: &str) -> std::io::Result<String> {
+        let input = "-- @query q() -> i64\nselect 1;\n";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        let doc = parser.parse_document().unwrap();
+        let options = Options {
+            dialect: Dialect::Sqlite,
+            type_overrides: Vec::new(),
+        };
+        let mut out = Vec::new();
+        Plugin::new(program.into()).generate(input, &doc, &options, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn plugin_receives_the_request_on_stdin() {
+        let output = generate("cat").unwrap();
+        assert!(output.starts_with(r#"{"version":2,"options":{"dialect":"sqlite","#));
+        assert!(output.ends_with("}\n"));
+    }
+
+    #[test]
+    fn plugin_that_ignores_its_input_succeeds() {
+        assert_eq!(generate("true").unwrap(), "");
+    }
+
+    #[test]
+    fn plugin_failure_is_an_error() {
+        let err = generate("false").unwrap_err();
+        assert!(err.to_string().starts_with("Plugin 'false' failed"));
+        let err = generate("querybinder-gen-does-not-exist").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+}
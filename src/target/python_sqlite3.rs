@@ -0,0 +1,370 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::collections::BTreeSet;
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type, TypedIdent};
+use crate::target::{collect_structs, query_text, Placeholder};
+use crate::Span;
+
+/// Python types for the simple types that can occur in annotations.
+///
+/// Simple types that are not in this table are used as-is.
+const TYPES: &[(&str, &str)] = &[
+    ("i8", "int"),
+    ("i16", "int"),
+    ("i32", "int"),
+    ("i64", "int"),
+    ("u8", "int"),
+    ("u16", "int"),
+    ("u32", "int"),
+    ("u64", "int"),
+    ("isize", "int"),
+    ("usize", "int"),
+    ("f32", "float"),
+    ("f64", "float"),
+    ("bool", "bool"),
+    ("&str", "str"),
+    ("String", "str"),
+    ("&[u8]", "bytes"),
+];
+
+/// Generate Python code that uses the `sqlite3` module from the standard library.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+    let structs = collect_structs(&doc);
+
+    let mut typing = BTreeSet::new();
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            collect_typing_imports(&query.annotation.result_type, &mut typing);
+            for param in &query.annotation.parameters {
+                collect_typing_imports(&param.type_, &mut typing);
+            }
+        }
+    }
+    for (_name, fields) in &structs {
+        for field in fields.iter() {
+            collect_typing_imports(&field.type_, &mut typing);
+        }
+    }
+
+    if !structs.is_empty() {
+        writeln!(out, "from dataclasses import dataclass")?;
+    }
+    writeln!(out, "from sqlite3 import Connection")?;
+    if !typing.is_empty() {
+        let names: Vec<&str> = typing.into_iter().collect();
+        writeln!(out, "from typing import {}", names.join(", "))?;
+    }
+
+    for (name, fields) in &structs {
+        write_dataclass(name, fields, out)?;
+    }
+
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            write_function(query, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add the names that need to be imported from `typing` to write the type.
+fn collect_typing_imports(type_: &Type<&str>, names: &mut BTreeSet<&'static str>) {
+    match type_ {
+        Type::Unit | Type::Simple(..) | Type::Struct(..) => {}
+        Type::Iterator(inner) => {
+            names.insert("Iterator");
+            collect_typing_imports(inner, names);
+        }
+        Type::Option(inner) => {
+            names.insert("Optional");
+            collect_typing_imports(inner, names);
+        }
+        Type::Tuple(ts) => {
+            names.insert("Tuple");
+            for t in ts {
+                collect_typing_imports(t, names);
+            }
+        }
+    }
+}
+
+/// Format a type as a Python type hint.
+fn format_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Unit => "None".to_string(),
+        Type::Simple(t) => match TYPES.iter().find(|(rust, _)| rust == t) {
+            Some((_, python)) => python.to_string(),
+            None => t.to_string(),
+        },
+        Type::Iterator(t) => format!("Iterator[{}]", format_type(t)),
+        Type::Option(t) => format!("Optional[{}]", format_type(t)),
+        // The empty tuple has a special syntax in type hints.
+        Type::Tuple(ts) if ts.is_empty() => "Tuple[()]".to_string(),
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = ts.iter().map(format_type).collect();
+            format!("Tuple[{}]", elements.join(", "))
+        }
+        Type::Struct(name, _fields) => name.to_string(),
+    }
+}
+
+/// Escape text for inclusion in a triple-quoted string literal.
+///
+/// A trailing quote is escaped as well, so the closing quotes can follow the
+/// text directly.
+fn escape_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    // The number of consecutive unescaped quotes before the current char.
+    let mut quotes = 0;
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '\\' => {
+                result.push_str("\\\\");
+                quotes = 0;
+            }
+            '"' if quotes == 2 || i + 1 == text.len() => {
+                result.push_str("\\\"");
+                quotes = 0;
+            }
+            '"' => {
+                result.push('"');
+                quotes += 1;
+            }
+            _ => {
+                result.push(ch);
+                quotes = 0;
+            }
+        }
+    }
+    result
+}
+
+fn write_dataclass(
+    name: &str,
+    fields: &[TypedIdent<&str>],
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    writeln!(out, "\n\n@dataclass")?;
+    writeln!(out, "class {}:", name)?;
+    for field in fields {
+        writeln!(out, "    {}: {}", field.ident, format_type(&field.type_))?;
+    }
+    Ok(())
+}
+
+/// Write the docstring of the function, if the query has documentation.
+fn write_docstring(docs: &[&str], out: &mut dyn io::Write) -> io::Result<()> {
+    // Doc comments usually have a space after the `--`, we don't want it here.
+    let lines: Vec<String> = docs
+        .iter()
+        .map(|line| escape_string(line.strip_prefix(' ').unwrap_or(line)))
+        .collect();
+    match lines.len() {
+        0 => Ok(()),
+        1 => writeln!(out, "    \"\"\"{}\"\"\"", lines[0]),
+        _ => {
+            writeln!(out, "    \"\"\"{}", lines[0])?;
+            for line in &lines[1..] {
+                if line.trim().is_empty() {
+                    writeln!(out)?;
+                } else {
+                    writeln!(out, "    {}", line)?;
+                }
+            }
+            writeln!(out, "    \"\"\"")
+        }
+    }
+}
+
+/// Format the expression that converts a row into the given type.
+fn format_read_row(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Struct(name, fields) => {
+            let args: Vec<String> = fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| format!("{}=row[{}]", field.ident, i))
+                .collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        Type::Tuple(ts) if ts.len() == 1 => "(row[0],)".to_string(),
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = (0..ts.len()).map(|i| format!("row[{}]", i)).collect();
+            format!("({})", elements.join(", "))
+        }
+        _ => "row[0]".to_string(),
+    }
+}
+
+fn write_function(query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    let annotation = &query.annotation;
+    let result_type = &annotation.result_type;
+
+    write!(out, "\n\ndef {}(connection: Connection", annotation.name)?;
+    for param in &annotation.parameters {
+        write!(out, ", {}: {}", param.ident, format_type(&param.type_))?;
+    }
+    writeln!(out, ") -> {}:", format_type(result_type))?;
+
+    write_docstring(&query.docs, out)?;
+
+    let (text, params) = query_text(query, Placeholder::Named);
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| format!("\"{}\": {}", param, param))
+        .collect();
+
+    match result_type {
+        Type::Unit => writeln!(out, "    connection.execute(")?,
+        _ => writeln!(out, "    cursor = connection.execute(")?,
+    }
+    writeln!(out, "        \"\"\"")?;
+    for line in escape_string(&text).lines() {
+        if line.trim().is_empty() {
+            writeln!(out)?;
+        } else {
+            writeln!(out, "        {}", line)?;
+        }
+    }
+    writeln!(out, "        \"\"\",")?;
+    writeln!(out, "        {{{}}},", params.join(", "))?;
+    writeln!(out, "    )")?;
+
+    match result_type {
+        Type::Unit => Ok(()),
+        Type::Option(inner) => {
+            writeln!(out, "    row = cursor.fetchone()")?;
+            writeln!(out, "    if row is None:")?;
+            writeln!(out, "        return None")?;
+            writeln!(out, "    return {}", format_read_row(inner))
+        }
+        Type::Iterator(inner) => {
+            writeln!(out, "    for row in cursor:")?;
+            writeln!(out, "        yield {}", format_read_row(inner))
+        }
+        other => {
+            writeln!(out, "    row = cursor.fetchone()")?;
+            writeln!(out, "    if row is None:")?;
+            writeln!(
+                out,
+                "        raise LookupError(\"Expected a row, but the query returned none.\")"
+            )?;
+            writeln!(out, "    return {}", format_read_row(other))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_string, process_file};
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: String"
+from users
+where name = :name;
+"#;
+        let expected = r#"from dataclasses import dataclass
+from sqlite3 import Connection
+from typing import Optional
+
+
+@dataclass
+class User:
+    id: int
+    name: str
+    email: str
+
+
+def get_user_by_name(connection: Connection, name: str) -> Optional[User]:
+    """Look up a user by username."""
+    cursor = connection.execute(
+        """
+        select id as id, name as name, email as email
+        from users
+        where name = :name;
+        """,
+        {"name": name},
+    )
+    row = cursor.fetchone()
+    if row is None:
+        return None
+    return User(id=row[0], name=row[1], email=row[2])
+"#;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_generates_generators_for_iterators() {
+        let input = "
+        -- Return the ids and names in a range,
+        -- ordered by id.
+        -- @query get_range(low: i64, high: Option<i64>) -> Iterator<(i64, String)>
+        select id as \"id: i64\", name as \"name: String\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input);
+        assert!(output.starts_with("from sqlite3 import Connection\n"));
+        assert!(output.contains("from typing import Iterator, Optional, Tuple\n"));
+        assert!(output.contains(
+            "def get_range(connection: Connection, low: int, high: Optional[int]) -> Iterator[Tuple[int, str]]:\n"
+        ));
+        assert!(output.contains(
+            "    \"\"\"Return the ids and names in a range,\n    ordered by id.\n    \"\"\"\n"
+        ));
+        assert!(output.contains("        {\"low\": low, \"high\": high},\n"));
+        assert!(output.contains("    for row in cursor:\n        yield (row[0], row[1])\n"));
+    }
+
+    #[test]
+    fn it_raises_when_a_required_row_is_missing() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input);
+        assert!(output.contains(
+            "def setup_schema(connection: Connection) -> None:\n    connection.execute(\n"
+        ));
+        assert!(output
+            .contains("def add_user(connection: Connection, name: str, email: str) -> int:\n"));
+        assert!(output.contains("        raise LookupError("));
+        assert!(output.contains("        {},\n"));
+    }
+
+    #[test]
+    fn escape_string_prevents_early_termination() {
+        assert_eq!(escape_string(r#"a\b"#), r#"a\\b"#);
+        assert_eq!(escape_string(r#"'"""'"#), r#"'""\"'"#);
+        assert_eq!(escape_string(r#"a "b""#), r#"a "b\""#);
+        assert_eq!(escape_string("\"\""), "\"\\\"");
+    }
+
+    #[test]
+    fn one_line_docstrings_can_end_in_a_quote() {
+        let input = r#"
+        -- Returns "x"
+        -- @query get_x() -> String
+        select 'x' as "x: String";
+        "#;
+        let output = generate(input);
+        assert!(output.contains("    \"\"\"Returns \"x\\\"\"\"\"\n"));
+    }
+}
@@ -0,0 +1,170 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Code generation that is shared between the different Rust targets.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type, TypedIdent};
+use crate::target::collect_structs;
+use crate::Span;
+
+/// The parts of Rust code generation that differ per database library.
+pub trait Library {
+    /// Write the `use` statements at the top of the file.
+    fn write_header(&self, doc: &Document<&str>, out: &mut dyn io::Write) -> io::Result<()>;
+
+    /// Attributes to put on the generated structs, such as derives.
+    fn struct_attributes(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Write the function for the query. Doc comments have been written already.
+    fn write_query(&self, query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()>;
+}
+
+/// Generate Rust code for the document, deferring to `library` for the details.
+pub fn process_file(
+    library: &dyn Library,
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+
+    library.write_header(&doc, out)?;
+
+    for (name, fields) in collect_structs(&doc) {
+        writeln!(out)?;
+        for attribute in library.struct_attributes() {
+            writeln!(out, "{}", attribute)?;
+        }
+        write_struct(name, fields, out)?;
+    }
+
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            writeln!(out)?;
+            for doc_line in &query.docs {
+                writeln!(out, "///{}", doc_line)?;
+            }
+            library.write_query(query, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Format a type as a Rust type.
+///
+/// Iterators have no direct counterpart, libraries format result iterators
+/// in their own way, this function formats them as `impl Iterator`.
+pub fn format_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Unit => "()".to_string(),
+        Type::Simple(t) => t.to_string(),
+        Type::Iterator(t) => format!("impl Iterator<Item = {}>", format_type(t)),
+        Type::Option(t) => format!("Option<{}>", format_type(t)),
+        // A one-tuple needs a trailing comma to not be a parenthesized type.
+        Type::Tuple(ts) if ts.len() == 1 => format!("({},)", format_type(&ts[0])),
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = ts.iter().map(format_type).collect();
+            format!("({})", elements.join(", "))
+        }
+        Type::Struct(name, _fields) => name.to_string(),
+    }
+}
+
+fn write_struct(
+    name: &str,
+    fields: &[TypedIdent<&str>],
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    writeln!(out, "pub struct {} {{", name)?;
+    for field in fields {
+        writeln!(
+            out,
+            "    pub {}: {},",
+            field.ident,
+            format_type(&field.type_)
+        )?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Write the function signature up to and including the opening brace.
+///
+/// The `connection` argument is the first argument, including its name, e.g.
+/// `connection: &Connection`. The query parameters follow it.
+pub fn write_signature(
+    query: &Query<&str>,
+    qualifiers: &str,
+    generics: &str,
+    connection: &str,
+    return_type: &str,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let annotation = &query.annotation;
+    write!(
+        out,
+        "{} {}{}({}",
+        qualifiers, annotation.name, generics, connection
+    )?;
+    for param in &annotation.parameters {
+        write!(out, ", {}: {}", param.ident, format_type(&param.type_))?;
+    }
+    writeln!(out, ") -> {} {{", return_type)
+}
+
+/// Write a raw string literal that contains `text`, on lines of its own.
+///
+/// The delimiters are indented by `indent`, but the text is written as-is,
+/// because indenting it would change string literals inside the query.
+pub fn write_raw_string(text: &str, indent: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    // Use one more '#' than the longest run of '#' that follows a '"' in the
+    // text, so the text cannot terminate the literal early.
+    let mut max_hashes = 0;
+    for (i, _) in text.match_indices('"') {
+        let n = text[i + 1..].bytes().take_while(|ch| *ch == b'#').count();
+        max_hashes = max_hashes.max(n);
+    }
+    let hashes = "#".repeat(max_hashes + 1);
+
+    writeln!(out, "{}r{}\"", indent, hashes)?;
+    writeln!(out, "{}", text)?;
+    write!(out, "{}\"{}", indent, hashes)
+}
+
+/// Write the expression that converts the current row into the given type.
+///
+/// `read_column` formats the expression that reads the column at an index.
+/// The expression starts at the current position, subsequent lines are
+/// indented by `indent`.
+pub fn write_read_row(
+    type_: &Type<&str>,
+    read_column: &dyn Fn(usize) -> String,
+    indent: &str,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    match type_ {
+        Type::Struct(name, fields) => {
+            writeln!(out, "{} {{", name)?;
+            for (i, field) in fields.iter().enumerate() {
+                writeln!(out, "{}    {}: {},", indent, field.ident, read_column(i))?;
+            }
+            write!(out, "{}}}", indent)
+        }
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = (0..ts.len()).map(read_column).collect();
+            match elements.len() {
+                1 => write!(out, "({},)", elements[0]),
+                _ => write!(out, "({})", elements.join(", ")),
+            }
+        }
+        _ => write!(out, "{}", read_column(0)),
+    }
+}
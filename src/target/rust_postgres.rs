@@ -0,0 +1,185 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Type};
+use crate::target::rust::{self, format_type, write_raw_string, write_read_row, write_signature};
+use crate::target::{query_text, Placeholder};
+use crate::Span;
+
+/// Generate Rust code that uses the synchronous `postgres` crate.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    rust::process_file(&Postgres, input, parsed, out)
+}
+
+struct Postgres;
+
+/// Format the expression that reads a column from the row.
+pub fn read_column(index: usize) -> String {
+    format!("row.get({})", index)
+}
+
+/// Format the parameters to bind as a slice, e.g. `&[&name, &email]`.
+pub fn format_params(params: &[&str]) -> String {
+    let params: Vec<String> = params.iter().map(|p| format!("&{}", p)).collect();
+    format!("&[{}]", params.join(", "))
+}
+
+/// Write `let {binding} = client.{method}(text, params){suffix}?;`.
+///
+/// When `binding` is empty, the result is discarded instead. The suffix is
+/// `.await` for asynchronous clients.
+pub fn write_call(
+    binding: &str,
+    method: &str,
+    text: &str,
+    params: &str,
+    suffix: &str,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    match binding {
+        "" => writeln!(out, "    client.{}(", method)?,
+        _ => writeln!(out, "    let {} = client.{}(", binding, method)?,
+    }
+    write_raw_string(text, "        ", out)?;
+    writeln!(out, ",")?;
+    writeln!(out, "        {},", params)?;
+    writeln!(out, "    ){}?;", suffix)
+}
+
+impl rust::Library for Postgres {
+    fn write_header(&self, _doc: &Document<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(out, "use postgres::{{Client, Error}};")
+    }
+
+    fn write_query(&self, query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        let result_type = &query.annotation.result_type;
+
+        let formatted_result_type = match result_type {
+            Type::Iterator(inner) => format!(
+                "Result<impl Iterator<Item = {}>, Error>",
+                format_type(inner)
+            ),
+            other => format!("Result<{}, Error>", format_type(other)),
+        };
+        let connection = "client: &mut Client";
+        write_signature(query, "pub fn", "", connection, &formatted_result_type, out)?;
+
+        let (text, params) = query_text(query, Placeholder::Dollar);
+        let params = format_params(&params);
+
+        match result_type {
+            Type::Unit => {
+                write_call("", "execute", &text, &params, "", out)?;
+                writeln!(out, "    Ok(())")?;
+            }
+            Type::Option(inner) => {
+                write_call("row", "query_opt", &text, &params, "", out)?;
+                write!(out, "    let result = row.map(|row| ")?;
+                write_read_row(inner, &read_column, "    ", out)?;
+                writeln!(out, ");")?;
+                writeln!(out, "    Ok(result)")?;
+            }
+            Type::Iterator(inner) => {
+                // The rows are in memory already, so we can convert them lazily.
+                write_call("rows", "query", &text, &params, "", out)?;
+                write!(out, "    let result = rows.into_iter().map(|row| ")?;
+                write_read_row(inner, &read_column, "    ", out)?;
+                writeln!(out, ");")?;
+                writeln!(out, "    Ok(result)")?;
+            }
+            other => {
+                write_call("row", "query_one", &text, &params, "", out)?;
+                write!(out, "    let result = ")?;
+                write_read_row(other, &read_column, "    ", out)?;
+                writeln!(out, ";")?;
+                writeln!(out, "    Ok(result)")?;
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: String"
+from users
+where name = :name;
+"#;
+        let expected = r##"use postgres::{Client, Error};
+
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+}
+
+/// Look up a user by username.
+pub fn get_user_by_name(client: &mut Client, name: &str) -> Result<Option<User>, Error> {
+    let row = client.query_opt(
+        r#"
+select id as id, name as name, email as email
+from users
+where name = $1;
+        "#,
+        &[&name],
+    )?;
+    let result = row.map(|row| User {
+        id: row.get(0),
+        name: row.get(1),
+        email: row.get(2),
+    });
+    Ok(result)
+}
+"##;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_numbers_parameters_by_first_occurrence() {
+        let input = "
+        -- @query get_range(low: i64, high: i64) -> Iterator<(i64, String)>
+        select id as \"id: i64\", name as \"name: String\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input);
+        assert!(output.contains("where id >= $1 and id < $2 and id <> $1;"));
+        assert!(output.contains("        &[&low, &high],"));
+        assert!(output
+            .contains("    let result = rows.into_iter().map(|row| (row.get(0), row.get(1)));"));
+    }
+
+    #[test]
+    fn it_executes_unit_queries() {
+        let input = "
+        -- @query delete_user(id: i64)
+        delete from users where id = :id;
+        ";
+        let output = generate(input);
+        assert!(output
+            .contains("pub fn delete_user(client: &mut Client, id: i64) -> Result<(), Error> {"));
+        assert!(output.contains("    client.execute(\n"));
+        assert!(output.contains("    Ok(())\n"));
+    }
+}
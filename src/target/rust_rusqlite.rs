@@ -0,0 +1,218 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type};
+use crate::target::rust::{self, format_type, write_raw_string, write_read_row, write_signature};
+use crate::target::{query_text, Placeholder};
+use crate::Span;
+
+/// Generate Rust code that uses the `rusqlite` crate.
+///
+/// The rows that `query_map` returns borrow the statement, so a function for
+/// an iterator query cannot prepare the statement itself and still return the
+/// rows. Instead, it takes the statement as argument, and a separate
+/// `prepare_` function prepares it.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    rust::process_file(&Rusqlite, input, parsed, out)
+}
+
+struct Rusqlite;
+
+/// Format the expression that reads a column from the row.
+fn read_column(index: usize) -> String {
+    format!("row.get({})?", index)
+}
+
+/// Format the `named_params!` invocation that binds the query parameters.
+fn format_params(params: &[&str]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| format!("\":{}\": {}", param, param))
+        .collect();
+    match params.len() {
+        0 => "named_params! {}".to_string(),
+        _ => format!("named_params! {{ {} }}", params.join(", ")),
+    }
+}
+
+/// Write the closure that converts a row into the given type.
+///
+/// Subsequent lines of the closure are indented by `indent`.
+fn write_row_closure(type_: &Type<&str>, indent: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    match type_ {
+        Type::Struct(..) | Type::Tuple(..) => {
+            writeln!(out, "|row| {{")?;
+            write!(out, "{}    Ok(", indent)?;
+            write_read_row(type_, &read_column, &format!("{}    ", indent), out)?;
+            writeln!(out, ")")?;
+            write!(out, "{}}}", indent)
+        }
+        _ => write!(out, "|row| row.get(0)"),
+    }
+}
+
+impl rust::Library for Rusqlite {
+    fn write_header(&self, doc: &Document<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        // Only import what we use, to avoid warnings.
+        let any_result = |f: fn(&Type<&str>) -> bool| {
+            doc.sections.iter().any(|section| match section {
+                Section::Query(query) => f(&query.annotation.result_type),
+                Section::Verbatim(..) => false,
+            })
+        };
+        let mut imports = vec!["named_params"];
+        if any_result(|t| matches!(t, Type::Iterator(..))) {
+            imports.push("CachedStatement");
+        }
+        imports.push("Connection");
+        if any_result(|t| matches!(t, Type::Option(..))) {
+            imports.push("OptionalExtension");
+        }
+        imports.push("Result");
+        writeln!(out, "use rusqlite::{{{}}};", imports.join(", "))
+    }
+
+    fn write_query(&self, query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        let result_type = &query.annotation.result_type;
+        let (text, params) = query_text(query, Placeholder::Named);
+        let params = format_params(&params);
+
+        if let Type::Iterator(inner) = result_type {
+            let iterator = format!(
+                "Result<impl Iterator<Item = Result<{}>> + 's>",
+                format_type(inner)
+            );
+            let statement = "statement: &'s mut CachedStatement<'_>";
+            write_signature(query, "pub fn", "<'s>", statement, &iterator, out)?;
+            write!(out, "    let rows = statement.query_map({}, ", params)?;
+            write_row_closure(inner, "    ", out)?;
+            writeln!(out, ")?;")?;
+            writeln!(out, "    Ok(rows)")?;
+            writeln!(out, "}}")?;
+
+            let name = query.annotation.name;
+            writeln!(out)?;
+            writeln!(out, "/// Prepare the statement to pass to [`{}`].", name)?;
+            writeln!(
+                out,
+                "pub fn prepare_{}(connection: &Connection) -> Result<CachedStatement<'_>> {{",
+                name
+            )?;
+            writeln!(out, "    connection.prepare_cached(")?;
+            write_raw_string(&text, "        ", out)?;
+            writeln!(out, "\n    )")?;
+            return writeln!(out, "}}");
+        }
+
+        let result = format!("Result<{}>", format_type(result_type));
+        let connection = "connection: &Connection";
+        write_signature(query, "pub fn", "", connection, &result, out)?;
+
+        writeln!(out, "    let mut statement = connection.prepare_cached(")?;
+        write_raw_string(&text, "        ", out)?;
+        writeln!(out, "\n    )?;")?;
+
+        match result_type {
+            Type::Unit => {
+                writeln!(out, "    statement.execute({})?;", params)?;
+                writeln!(out, "    Ok(())")?;
+            }
+            Type::Option(inner) => {
+                writeln!(out, "    statement")?;
+                write!(out, "        .query_row({}, ", params)?;
+                write_row_closure(inner, "        ", out)?;
+                writeln!(out, ")")?;
+                writeln!(out, "        .optional()")?;
+            }
+            Type::Iterator(..) => unreachable!("Iterators are handled above."),
+            other => {
+                write!(out, "    statement.query_row({}, ", params)?;
+                write_row_closure(other, "    ", out)?;
+                writeln!(out, ")")?;
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: String"
+from users
+where name = :name;
+"#;
+        let expected = r##"use rusqlite::{named_params, Connection, OptionalExtension, Result};
+
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+}
+
+/// Look up a user by username.
+pub fn get_user_by_name(connection: &Connection, name: &str) -> Result<Option<User>> {
+    let mut statement = connection.prepare_cached(
+        r#"
+select id as id, name as name, email as email
+from users
+where name = :name;
+        "#
+    )?;
+    statement
+        .query_row(named_params! { ":name": name }, |row| {
+            Ok(User {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                email: row.get(2)?,
+            })
+        })
+        .optional()
+}
+"##;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_returns_lazy_iterators_and_executes_unit_queries() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input);
+        assert!(
+            output.contains("use rusqlite::{named_params, CachedStatement, Connection, Result};")
+        );
+        assert!(output.contains(
+            "pub fn list_all_users<'s>(statement: &'s mut CachedStatement<'_>) -> Result<impl Iterator<Item = Result<User>> + 's> {"
+        ));
+        assert!(output.contains("    let rows = statement.query_map(named_params! {}, |row| {"));
+        assert!(output.contains("    Ok(rows)\n}\n"));
+        assert!(output.contains(
+            "/// Prepare the statement to pass to [`list_all_users`].\npub fn prepare_list_all_users(connection: &Connection) -> Result<CachedStatement<'_>> {\n    connection.prepare_cached(\n"
+        ));
+        assert!(!output.contains("collect"));
+        assert!(output.contains("    statement.execute(named_params! {})?;"));
+        assert!(output.contains(
+            "    statement.query_row(named_params! { \":name\": name, \":email\": email }, |row| row.get(0))"
+        ));
+    }
+}
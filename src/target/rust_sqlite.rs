@@ -0,0 +1,254 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Type};
+use crate::target::rust::{self, format_type, write_raw_string, write_read_row, write_signature};
+use crate::target::{query_text, Placeholder};
+use crate::Span;
+
+/// Generate Rust code that uses the `sqlite` crate.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    rust::process_file(&Sqlite, input, parsed, out)
+}
+
+struct Sqlite;
+
+/// Format the expression that reads a column from the current row.
+fn read_column(index: usize) -> String {
+    format!("statement.read({})?", index)
+}
+
+/// Write the `match` on the next row, with `indent` as the base indentation.
+///
+/// When there is a row, the match evaluates to `Ok(Some(result))` for
+/// `optional`, and to `Ok(result)` otherwise.
+fn write_match_next(
+    type_: &Type<&str>,
+    optional: bool,
+    indent: &str,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    writeln!(out, "{}match statement.next()? {{", indent)?;
+    if optional {
+        writeln!(out, "{}    State::Done => Ok(None),", indent)?;
+    } else {
+        writeln!(out, "{}    State::Done => Err(sqlite::Error {{", indent)?;
+        writeln!(out, "{}        code: None,", indent)?;
+        writeln!(
+            out,
+            "{}        message: Some(\"Expected a row, but the query returned none.\".to_string()),",
+            indent
+        )?;
+        writeln!(out, "{}    }}),", indent)?;
+    }
+    writeln!(out, "{}    State::Row => {{", indent)?;
+    write!(out, "{}        let result = ", indent)?;
+    write_read_row(type_, &read_column, &format!("{}        ", indent), out)?;
+    writeln!(out, ";")?;
+    if optional {
+        writeln!(out, "{}        Ok(Some(result))", indent)?;
+    } else {
+        writeln!(out, "{}        Ok(result)", indent)?;
+    }
+    writeln!(out, "{}    }}", indent)?;
+    writeln!(out, "{}}}", indent)
+}
+
+impl rust::Library for Sqlite {
+    fn write_header(&self, _doc: &Document<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(out, "use sqlite::{{Connection, Result, State}};")
+    }
+
+    fn write_query(&self, query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        let result_type = &query.annotation.result_type;
+
+        // An iterator borrows the statement, which borrows the connection, so
+        // the result needs to carry the lifetime of the connection.
+        match result_type {
+            Type::Iterator(inner) => {
+                let iterator = format!(
+                    "Result<impl Iterator<Item = Result<{}>> + 'a>",
+                    format_type(inner)
+                );
+                let connection = "connection: &'a Connection";
+                write_signature(query, "pub fn", "<'a>", connection, &iterator, out)?;
+            }
+            _ => {
+                let result = format!("Result<{}>", format_type(result_type));
+                let connection = "connection: &Connection";
+                write_signature(query, "pub fn", "", connection, &result, out)?;
+            }
+        }
+
+        let (text, params) = query_text(query, Placeholder::Named);
+        writeln!(out, "    let mut statement = connection.prepare(")?;
+        write_raw_string(&text, "        ", out)?;
+        writeln!(out, "\n    )?;")?;
+
+        for (i, param) in params.iter().enumerate() {
+            writeln!(out, "    statement.bind({}, {})?;", i + 1, param)?;
+        }
+
+        match result_type {
+            Type::Unit => {
+                writeln!(out, "    while statement.next()? != State::Done {{}}")?;
+                writeln!(out, "    Ok(())")?;
+            }
+            Type::Option(inner) => {
+                write_match_next(inner, true, "    ", out)?;
+            }
+            Type::Iterator(inner) => {
+                writeln!(
+                    out,
+                    "    let mut next_row = move || -> Result<Option<{}>> {{",
+                    format_type(inner)
+                )?;
+                write_match_next(inner, true, "        ", out)?;
+                writeln!(out, "    }};")?;
+                writeln!(
+                    out,
+                    "    Ok(std::iter::from_fn(move || next_row().transpose()))"
+                )?;
+            }
+            other => {
+                write_match_next(other, false, "    ", out)?;
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn it_generates_a_function_for_an_optional_scalar() {
+        let input = r#"-- Look up the email address of a user.
+-- @query get_email_by_name(name: &str) -> Option<String>
+select email as "email: String"
+from users
+where name = :name;
+"#;
+        let expected = r##"use sqlite::{Connection, Result, State};
+
+/// Look up the email address of a user.
+pub fn get_email_by_name(connection: &Connection, name: &str) -> Result<Option<String>> {
+    let mut statement = connection.prepare(
+        r#"
+select email as email
+from users
+where name = :name;
+        "#
+    )?;
+    statement.bind(1, name)?;
+    match statement.next()? {
+        State::Done => Ok(None),
+        State::Row => {
+            let result = statement.read(0)?;
+            Ok(Some(result))
+        }
+    }
+}
+"##;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: String"
+from users
+where name = :name;
+"#;
+        let expected = r##"use sqlite::{Connection, Result, State};
+
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+}
+
+/// Look up a user by username.
+pub fn get_user_by_name(connection: &Connection, name: &str) -> Result<Option<User>> {
+    let mut statement = connection.prepare(
+        r#"
+select id as id, name as name, email as email
+from users
+where name = :name;
+        "#
+    )?;
+    statement.bind(1, name)?;
+    match statement.next()? {
+        State::Done => Ok(None),
+        State::Row => {
+            let result = User {
+                id: statement.read(0)?,
+                name: statement.read(1)?,
+                email: statement.read(2)?,
+            };
+            Ok(Some(result))
+        }
+    }
+}
+"##;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_generates_a_struct_once_per_name() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input);
+        assert_eq!(output.matches("pub struct User {").count(), 1);
+        assert_eq!(output.matches("pub struct NewUser {").count(), 1);
+    }
+
+    #[test]
+    fn it_binds_repeated_parameters_once() {
+        let input = "
+        -- @query get_range(low: i64, high: i64) -> Iterator<(i64, String)>
+        select id as \"id: i64\", name as \"name: String\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input);
+        assert!(output.contains("statement.bind(1, low)?;"));
+        assert!(output.contains("statement.bind(2, high)?;"));
+        assert!(!output.contains("statement.bind(3"));
+        assert!(output.contains("let result = (statement.read(0)?, statement.read(1)?);"));
+    }
+
+    #[test]
+    fn it_uses_enough_hashes_in_raw_strings() {
+        let input = "
+        -- @query get_hash() -> String
+        select '\"#' as \"hash: String\";
+        ";
+        let output = generate(input);
+        assert!(output.contains("r##\"\n"));
+        assert!(output.contains("\"##\n"));
+    }
+
+    #[test]
+    fn it_keeps_the_query_text_unchanged() {
+        let input = "-- @query get_text() -> String\nselect 'a\n\n  b' as \"t: String\";\n";
+        let output = generate(input);
+        assert!(output.contains("        r#\"\nselect 'a\n\n  b' as t;\n        \"#\n"));
+    }
+}
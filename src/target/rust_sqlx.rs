@@ -0,0 +1,201 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Type};
+use crate::dialect::Dialect;
+use crate::target::rust::{self, format_type, write_raw_string, write_signature};
+use crate::target::{query_text, Placeholder};
+use crate::Span;
+
+/// Generate Rust code that uses the `sqlx` crate.
+///
+/// Unlike the `sqlx::query!` macros, the generated code does not need access
+/// to a database at compile time.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    dialect: Dialect,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    rust::process_file(&Sqlx { dialect }, input, parsed, out)
+}
+
+struct Sqlx {
+    dialect: Dialect,
+}
+
+impl Sqlx {
+    fn database(&self) -> &'static str {
+        match self.dialect {
+            Dialect::Sqlite => "Sqlite",
+            Dialect::Postgres => "Postgres",
+            Dialect::Mysql => "MySql",
+        }
+    }
+
+    fn placeholder(&self) -> Placeholder {
+        match self.dialect {
+            Dialect::Sqlite => Placeholder::QuestionNumbered,
+            Dialect::Postgres => Placeholder::Dollar,
+            Dialect::Mysql => Placeholder::Question,
+        }
+    }
+}
+
+/// Return the query function to build the query with, for the given row type.
+fn query_function(row_type: &Type<&str>) -> String {
+    match row_type {
+        Type::Unit => "sqlx::query".to_string(),
+        Type::Simple(..) => format!("sqlx::query_scalar::<_, {}>", format_type(row_type)),
+        _ => format!("sqlx::query_as::<_, {}>", format_type(row_type)),
+    }
+}
+
+impl rust::Library for Sqlx {
+    fn write_header(&self, _doc: &Document<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(out, "use sqlx::{{Executor, Result, {}}};", self.database())
+    }
+
+    fn struct_attributes(&self) -> &'static [&'static str] {
+        &["#[derive(sqlx::FromRow)]"]
+    }
+
+    fn write_query(&self, query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        let result_type = &query.annotation.result_type;
+
+        // The stream that `fetch` returns borrows the query and the executor,
+        // so we fetch all rows into a vector instead.
+        let (row_type, formatted_result_type, fetch) = match result_type {
+            Type::Unit => (result_type, "Result<()>".to_string(), "execute"),
+            Type::Option(inner) => (
+                &**inner,
+                format!("Result<{}>", format_type(result_type)),
+                "fetch_optional",
+            ),
+            Type::Iterator(inner) => (
+                &**inner,
+                format!("Result<Vec<{}>>", format_type(inner)),
+                "fetch_all",
+            ),
+            other => (
+                other,
+                format!("Result<{}>", format_type(other)),
+                "fetch_one",
+            ),
+        };
+        let connection = format!(
+            "executor: impl Executor<'e, Database = {}>",
+            self.database()
+        );
+        write_signature(
+            query,
+            "pub async fn",
+            "<'e>",
+            &connection,
+            &formatted_result_type,
+            out,
+        )?;
+
+        let (text, params) = query_text(query, self.placeholder());
+        writeln!(out, "    {}(", query_function(row_type))?;
+        write_raw_string(&text, "        ", out)?;
+        writeln!(out, ",")?;
+        writeln!(out, "    )")?;
+
+        for param in &params {
+            writeln!(out, "    .bind({})", param)?;
+        }
+
+        writeln!(out, "    .{}(executor)", fetch)?;
+        match result_type {
+            Type::Unit => {
+                writeln!(out, "    .await?;")?;
+                writeln!(out, "    Ok(())")?;
+            }
+            _ => writeln!(out, "    .await")?,
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::dialect::Dialect;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str, dialect: Dialect) -> String {
+        generate_for_test(input, |input, doc, out| {
+            process_file(input, doc, dialect, out)
+        })
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: String"
+from users
+where name = :name;
+"#;
+        let expected = r##"use sqlx::{Executor, Result, Sqlite};
+
+#[derive(sqlx::FromRow)]
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+}
+
+/// Look up a user by username.
+pub async fn get_user_by_name<'e>(executor: impl Executor<'e, Database = Sqlite>, name: &str) -> Result<Option<User>> {
+    sqlx::query_as::<_, User>(
+        r#"
+select id as id, name as name, email as email
+from users
+where name = ?1;
+        "#,
+    )
+    .bind(name)
+    .fetch_optional(executor)
+    .await
+}
+"##;
+        assert_eq!(generate(input, Dialect::Sqlite), expected);
+    }
+
+    #[test]
+    fn it_uses_the_placeholders_of_the_dialect() {
+        let input = "
+        -- @query get_range(low: i64, high: i64) -> Iterator<(i64, String)>
+        select id as \"id: i64\", name as \"name: String\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input, Dialect::Sqlite);
+        assert!(output.contains("where id >= ?1 and id < ?2 and id <> ?1;"));
+        assert!(output.contains("-> Result<Vec<(i64, String)>> {"));
+        assert!(output.contains("    sqlx::query_as::<_, (i64, String)>(\n"));
+        assert!(output.contains("    .bind(low)\n    .bind(high)\n    .fetch_all(executor)\n"));
+
+        let output = generate(input, Dialect::Postgres);
+        assert!(output.starts_with("use sqlx::{Executor, Result, Postgres};"));
+        assert!(output.contains("where id >= $1 and id < $2 and id <> $1;"));
+    }
+
+    #[test]
+    fn it_executes_unit_queries_and_fetches_scalars() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input, Dialect::Sqlite);
+        assert!(output.contains("    sqlx::query(\n"));
+        assert!(output.contains("    .execute(executor)\n    .await?;\n    Ok(())\n"));
+        assert!(output.contains("    sqlx::query_scalar::<_, i64>(\n"));
+        assert!(output.contains("    .fetch_one(executor)\n    .await\n"));
+    }
+}
@@ -0,0 +1,166 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type};
+use crate::target::rust::{self, format_type, write_read_row, write_signature};
+use crate::target::rust_postgres::{format_params, read_column, write_call};
+use crate::target::{query_text, Placeholder};
+use crate::Span;
+
+/// Generate Rust code that uses the asynchronous `tokio-postgres` crate.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    rust::process_file(&TokioPostgres, input, parsed, out)
+}
+
+struct TokioPostgres;
+
+impl rust::Library for TokioPostgres {
+    fn write_header(&self, doc: &Document<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        // Only streams need the extra imports, and unused ones cause warnings.
+        let any_iterator = doc.sections.iter().any(|section| match section {
+            Section::Query(query) => matches!(query.annotation.result_type, Type::Iterator(..)),
+            Section::Verbatim(..) => false,
+        });
+        if any_iterator {
+            writeln!(out, "use futures::{{Stream, TryStreamExt}};")?;
+            writeln!(out, "use tokio_postgres::types::ToSql;")?;
+        }
+        writeln!(out, "use tokio_postgres::{{Error, GenericClient}};")
+    }
+
+    fn write_query(&self, query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+        let result_type = &query.annotation.result_type;
+
+        let formatted_result_type = match result_type {
+            Type::Iterator(inner) => format!(
+                "Result<impl Stream<Item = Result<{}, Error>>, Error>",
+                format_type(inner)
+            ),
+            other => format!("Result<{}, Error>", format_type(other)),
+        };
+        let connection = "client: &impl GenericClient";
+        write_signature(
+            query,
+            "pub async fn",
+            "",
+            connection,
+            &formatted_result_type,
+            out,
+        )?;
+
+        let (text, params) = query_text(query, Placeholder::Dollar);
+
+        match result_type {
+            Type::Unit => {
+                let params = format_params(&params);
+                write_call("", "execute", &text, &params, ".await", out)?;
+                writeln!(out, "    Ok(())")?;
+            }
+            Type::Option(inner) => {
+                let params = format_params(&params);
+                write_call("row", "query_opt", &text, &params, ".await", out)?;
+                write!(out, "    let result = row.map(|row| ")?;
+                write_read_row(inner, &read_column, "    ", out)?;
+                writeln!(out, ");")?;
+                writeln!(out, "    Ok(result)")?;
+            }
+            Type::Iterator(inner) => {
+                // Unlike `query`, `query_raw` does not buffer the rows, it
+                // returns a stream, and it takes the parameters by iterator.
+                let refs: Vec<String> = params.iter().map(|p| format!("&{}", p)).collect();
+                writeln!(
+                    out,
+                    "    let params: [&(dyn ToSql + Sync); {}] = [{}];",
+                    refs.len(),
+                    refs.join(", ")
+                )?;
+                write_call("rows", "query_raw", &text, "params", ".await", out)?;
+                write!(out, "    let result = rows.map_ok(|row| ")?;
+                write_read_row(inner, &read_column, "    ", out)?;
+                writeln!(out, ");")?;
+                writeln!(out, "    Ok(result)")?;
+            }
+            other => {
+                let params = format_params(&params);
+                write_call("row", "query_one", &text, &params, ".await", out)?;
+                write!(out, "    let result = ")?;
+                write_read_row(other, &read_column, "    ", out)?;
+                writeln!(out, ";")?;
+                writeln!(out, "    Ok(result)")?;
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn it_generates_a_stream_for_iterators() {
+        let input = r#"-- Iterate over users in a range of ids.
+-- @query iter_users(low: i64, high: i64) -> Iterator<User>
+select id as "id: i64", name as "name: String"
+from users
+where id >= :low and id < :high and id <> :low;
+"#;
+        let expected = r##"use futures::{Stream, TryStreamExt};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Error, GenericClient};
+
+pub struct User {
+    pub id: i64,
+    pub name: String,
+}
+
+/// Iterate over users in a range of ids.
+pub async fn iter_users(client: &impl GenericClient, low: i64, high: i64) -> Result<impl Stream<Item = Result<User, Error>>, Error> {
+    let params: [&(dyn ToSql + Sync); 2] = [&low, &high];
+    let rows = client.query_raw(
+        r#"
+select id as id, name as name
+from users
+where id >= $1 and id < $2 and id <> $1;
+        "#,
+        params,
+    ).await?;
+    let result = rows.map_ok(|row| User {
+        id: row.get(0),
+        name: row.get(1),
+    });
+    Ok(result)
+}
+"##;
+        assert_eq!(generate(input), expected);
+    }
+
+    #[test]
+    fn it_awaits_optional_queries() {
+        let input = "
+        -- @query get_name(id: i64) -> Option<String>
+        select name as \"name: String\" from users where id = :id;
+        ";
+        let output = generate(input);
+        assert!(output.starts_with("use tokio_postgres::{Error, GenericClient};\n\n"));
+        assert!(output.contains("    let row = client.query_opt(\n"));
+        assert!(output.contains("        &[&id],\n    ).await?;\n"));
+        assert!(output.contains("    let result = row.map(|row| row.get(0));"));
+    }
+}
@@ -0,0 +1,531 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type};
+use crate::target::{collect_structs, query_text, to_camel_case, Placeholder};
+use crate::Span;
+
+/// How a value of a Swift type is bound and read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Int32,
+    Int64,
+    Double,
+    Bool,
+    Text,
+    /// A type that we don't know, it must conform to `SQLiteValue`.
+    Custom,
+}
+
+/// Swift types for the simple types that can occur in annotations.
+///
+/// Simple types that are not in this table are used as-is, and must conform
+/// to the `SQLiteValue` protocol that the generated code defines in that case.
+const TYPES: &[(&str, &str, Kind)] = &[
+    ("i8", "Int8", Kind::Int32),
+    ("i16", "Int16", Kind::Int32),
+    ("i32", "Int32", Kind::Int32),
+    ("i64", "Int64", Kind::Int64),
+    ("u8", "UInt8", Kind::Int32),
+    ("u16", "UInt16", Kind::Int32),
+    ("u32", "UInt32", Kind::Int64),
+    ("f32", "Float", Kind::Double),
+    ("f64", "Double", Kind::Double),
+    ("bool", "Bool", Kind::Bool),
+    ("&str", "String", Kind::Text),
+    ("String", "String", Kind::Text),
+];
+
+const SUPPORT: &str = r#"import SQLite3
+
+public struct SQLiteError: Error {
+    public let code: Int32
+    public let message: String
+}
+
+private let SQLITE_TRANSIENT = unsafeBitCast(-1, to: sqlite3_destructor_type.self)
+
+private func check(_ db: OpaquePointer, _ code: Int32) throws {
+    if code != SQLITE_OK {
+        throw SQLiteError(code: code, message: String(cString: sqlite3_errmsg(db)))
+    }
+}
+
+private func prepare(_ db: OpaquePointer, _ sql: String) throws -> OpaquePointer {
+    var statement: OpaquePointer?
+    try check(db, sqlite3_prepare_v2(db, sql, -1, &statement, nil))
+    return statement!
+}
+
+/// Step the statement, return whether it produced a row.
+private func step(_ db: OpaquePointer, _ statement: OpaquePointer) throws -> Bool {
+    let code = sqlite3_step(statement)
+    switch code {
+    case SQLITE_ROW: return true
+    case SQLITE_DONE: return false
+    default: throw SQLiteError(code: code, message: String(cString: sqlite3_errmsg(db)))
+    }
+}
+"#;
+
+const ROWS: &str = r#"
+/// The rows of a query, which are read lazily.
+///
+/// The statement is finalized when the sequence is released. When stepping
+/// fails, the sequence ends, and `error` holds the reason.
+public final class Rows<Element>: Sequence, IteratorProtocol {
+    private let db: OpaquePointer
+    private let statement: OpaquePointer
+    private let read: (OpaquePointer) -> Element
+    public private(set) var error: SQLiteError?
+
+    fileprivate init(_ db: OpaquePointer, _ statement: OpaquePointer, read: @escaping (OpaquePointer) -> Element) {
+        self.db = db
+        self.statement = statement
+        self.read = read
+    }
+
+    deinit {
+        sqlite3_finalize(statement)
+    }
+
+    public func next() -> Element? {
+        do {
+            return try step(db, statement) ? read(statement) : nil
+        } catch let error as SQLiteError {
+            self.error = error
+            return nil
+        } catch {
+            return nil
+        }
+    }
+}
+"#;
+
+const SQLITE_VALUE: &str = r#"
+/// A type that can be bound to and read from a statement.
+///
+/// Types in annotations that have no built-in mapping must conform to this.
+public protocol SQLiteValue {
+    init(statement: OpaquePointer, index: Int32)
+    func bind(to statement: OpaquePointer, index: Int32) -> Int32
+}
+"#;
+
+/// Generate Swift code that uses the sqlite3 C API.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+    let structs = collect_structs(&doc);
+    let queries: Vec<&Query<&str>> = doc
+        .sections
+        .iter()
+        .filter_map(|section| match section {
+            Section::Query(query) => Some(query),
+            Section::Verbatim(..) => None,
+        })
+        .collect();
+
+    let any_iterator = queries
+        .iter()
+        .any(|q| matches!(q.annotation.result_type, Type::Iterator(..)));
+    let any_custom = queries.iter().any(|q| {
+        has_custom_type(&q.annotation.result_type)
+            || q.annotation
+                .parameters
+                .iter()
+                .any(|p| has_custom_type(&p.type_))
+    });
+
+    write!(out, "{}", SUPPORT)?;
+    if any_iterator {
+        write!(out, "{}", ROWS)?;
+    }
+    if any_custom {
+        write!(out, "{}", SQLITE_VALUE)?;
+    }
+
+    for (name, fields) in &structs {
+        // `SQLiteValue` does not imply `Decodable`, so a struct with a field of
+        // a custom type cannot have `Decodable` synthesized.
+        let conformance = if fields.iter().any(|f| has_custom_type(&f.type_)) {
+            ""
+        } else {
+            ": Decodable"
+        };
+        writeln!(out)?;
+        writeln!(out, "public struct {}{} {{", name, conformance)?;
+        for field in fields.iter() {
+            writeln!(
+                out,
+                "    public let {}: {}",
+                to_camel_case(field.ident, false),
+                format_type(&field.type_)
+            )?;
+        }
+        writeln!(out, "}}")?;
+    }
+
+    for query in &queries {
+        write_function(query, out)?;
+    }
+
+    Ok(())
+}
+
+/// Look up the Swift type and kind of a simple type.
+fn lookup(type_: &str) -> (&str, Kind) {
+    match TYPES.iter().find(|(rust, _, _)| *rust == type_) {
+        Some((_, swift, kind)) => (swift, *kind),
+        None => (type_, Kind::Custom),
+    }
+}
+
+/// Whether the type contains a simple type that needs `SQLiteValue`.
+fn has_custom_type(type_: &Type<&str>) -> bool {
+    match type_ {
+        Type::Unit => false,
+        Type::Simple(t) => lookup(t).1 == Kind::Custom,
+        Type::Iterator(inner) | Type::Option(inner) => has_custom_type(inner),
+        Type::Tuple(ts) => ts.iter().any(has_custom_type),
+        Type::Struct(_name, fields) => fields.iter().any(|f| has_custom_type(&f.type_)),
+    }
+}
+
+/// Format a type as a Swift type.
+fn format_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Unit => "Void".to_string(),
+        Type::Simple(t) => lookup(t).0.to_string(),
+        Type::Iterator(t) => format!("Rows<{}>", format_type(t)),
+        Type::Option(t) => format!("{}?", format_type(t)),
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = ts.iter().map(format_type).collect();
+            format!("({})", elements.join(", "))
+        }
+        Type::Struct(name, _fields) => name.to_string(),
+    }
+}
+
+/// Format the expression that reads the 0-based column `index`.
+fn read_column(type_: &Type<&str>, index: usize) -> String {
+    match type_ {
+        Type::Option(inner) => format!(
+            "sqlite3_column_type(statement, {}) == SQLITE_NULL ? nil : {}",
+            index,
+            read_column(inner, index)
+        ),
+        Type::Simple(t) => {
+            let (swift, kind) = lookup(t);
+            let (read, native) = match kind {
+                Kind::Int32 => (format!("sqlite3_column_int(statement, {})", index), "Int32"),
+                Kind::Int64 => (
+                    format!("sqlite3_column_int64(statement, {})", index),
+                    "Int64",
+                ),
+                Kind::Double => (
+                    format!("sqlite3_column_double(statement, {})", index),
+                    "Double",
+                ),
+                Kind::Bool => (
+                    format!("sqlite3_column_int(statement, {}) != 0", index),
+                    "Bool",
+                ),
+                Kind::Text => (
+                    format!("String(cString: sqlite3_column_text(statement, {}))", index),
+                    "String",
+                ),
+                Kind::Custom => (
+                    format!("{}(statement: statement, index: {})", swift, index),
+                    swift,
+                ),
+            };
+            if swift == native {
+                read
+            } else {
+                format!("{}({})", swift, read)
+            }
+        }
+        // Structs and tuples cannot be nested in a column.
+        _ => "()".to_string(),
+    }
+}
+
+/// Format the expression that binds a value to the 1-based `index`.
+fn bind_value(type_: &Type<&str>, index: usize, value: &str) -> String {
+    match type_ {
+        Type::Option(inner) => format!(
+            "{} == nil ? sqlite3_bind_null(statement, {}) : {}",
+            value,
+            index,
+            bind_value(inner, index, &format!("{}!", value))
+        ),
+        Type::Simple(t) => {
+            let (swift, kind) = lookup(t);
+            let convert = |native: &str| -> String {
+                if swift == native {
+                    value.to_string()
+                } else {
+                    format!("{}({})", native, value)
+                }
+            };
+            match kind {
+                Kind::Int32 => format!(
+                    "sqlite3_bind_int(statement, {}, {})",
+                    index,
+                    convert("Int32")
+                ),
+                Kind::Int64 => format!(
+                    "sqlite3_bind_int64(statement, {}, {})",
+                    index,
+                    convert("Int64")
+                ),
+                Kind::Double => format!(
+                    "sqlite3_bind_double(statement, {}, {})",
+                    index,
+                    convert("Double")
+                ),
+                Kind::Bool => format!("sqlite3_bind_int(statement, {}, {} ? 1 : 0)", index, value),
+                Kind::Text => format!(
+                    "sqlite3_bind_text(statement, {}, {}, -1, SQLITE_TRANSIENT)",
+                    index, value
+                ),
+                Kind::Custom => format!("{}.bind(to: statement, index: {})", value, index),
+            }
+        }
+        _ => format!("sqlite3_bind_null(statement, {})", index),
+    }
+}
+
+/// Format the expression that reads the current row as the given type.
+fn read_row(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Struct(name, fields) => {
+            let args: Vec<String> = fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    format!(
+                        "{}: {}",
+                        to_camel_case(field.ident, false),
+                        read_column(&field.type_, i)
+                    )
+                })
+                .collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = ts
+                .iter()
+                .enumerate()
+                .map(|(i, t)| read_column(t, i))
+                .collect();
+            format!("({})", elements.join(", "))
+        }
+        other => read_column(other, 0),
+    }
+}
+
+/// Write the query as a multi-line string literal, indented by `indent`.
+fn write_string_literal(text: &str, indent: &str, out: &mut dyn io::Write) -> io::Result<()> {
+    let escaped = text.replace('\\', "\\\\").replace("\"\"\"", "\\\"\"\"");
+    writeln!(out, "\"\"\"")?;
+    for line in escaped.lines() {
+        if line.trim().is_empty() {
+            writeln!(out)?;
+        } else {
+            writeln!(out, "{}{}", indent, line)?;
+        }
+    }
+    write!(out, "{}\"\"\"", indent)
+}
+
+fn write_function(query: &Query<&str>, out: &mut dyn io::Write) -> io::Result<()> {
+    let annotation = &query.annotation;
+    let result_type = &annotation.result_type;
+
+    writeln!(out)?;
+    for doc_line in &query.docs {
+        writeln!(out, "///{}", doc_line)?;
+    }
+    write!(
+        out,
+        "public func {}(_ db: OpaquePointer",
+        to_camel_case(annotation.name, false)
+    )?;
+    for param in &annotation.parameters {
+        write!(
+            out,
+            ", {}: {}",
+            to_camel_case(param.ident, false),
+            format_type(&param.type_)
+        )?;
+    }
+    match result_type {
+        Type::Unit => writeln!(out, ") throws {{")?,
+        other => writeln!(out, ") throws -> {} {{", format_type(other))?,
+    }
+
+    let (text, params) = query_text(query, Placeholder::Named);
+    write!(out, "    let statement = try prepare(db, ")?;
+    write_string_literal(&text, "        ", out)?;
+    writeln!(out, ")")?;
+
+    // The rows own the statement, for other results we finalize it here.
+    match result_type {
+        Type::Iterator(inner) => {
+            writeln!(out, "    let rows = Rows(db, statement) {{ statement in")?;
+            writeln!(out, "        {}", read_row(inner))?;
+            writeln!(out, "    }}")?;
+        }
+        _ => writeln!(out, "    defer {{ sqlite3_finalize(statement) }}")?,
+    }
+
+    // SQLite numbers named parameters in order of first occurrence.
+    for (i, name) in params.iter().enumerate() {
+        let declared = annotation
+            .parameters
+            .iter()
+            .find(|param| param.ident == *name);
+        let type_ = match declared {
+            Some(param) => &param.type_,
+            None => &Type::Unit,
+        };
+        writeln!(
+            out,
+            "    try check(db, {})",
+            bind_value(type_, i + 1, &to_camel_case(name, false))
+        )?;
+    }
+
+    match result_type {
+        Type::Unit => {
+            writeln!(out, "    while try step(db, statement) {{}}")?;
+        }
+        Type::Iterator(..) => {
+            writeln!(out, "    return rows")?;
+        }
+        Type::Option(inner) => {
+            writeln!(
+                out,
+                "    guard try step(db, statement) else {{ return nil }}"
+            )?;
+            writeln!(out, "    return {}", read_row(inner))?;
+        }
+        other => {
+            writeln!(out, "    guard try step(db, statement) else {{")?;
+            writeln!(
+                out,
+                "        throw SQLiteError(code: SQLITE_DONE, message: \"Expected a row, but the query returned none.\")"
+            )?;
+            writeln!(out, "    }}")?;
+            writeln!(out, "    return {}", read_row(other))?;
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::target::generate_for_test;
+
+    fn generate(input: &str) -> String {
+        generate_for_test(input, process_file)
+    }
+
+    #[test]
+    fn it_generates_the_readme_example() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: Option<String>"
+from users
+where name = :name;
+"#;
+        let expected = r#"
+public struct User: Decodable {
+    public let id: Int64
+    public let name: String
+    public let email: String?
+}
+
+/// Look up a user by username.
+public func getUserByName(_ db: OpaquePointer, name: String) throws -> User? {
+    let statement = try prepare(db, """
+        select id as id, name as name, email as email
+        from users
+        where name = :name;
+        """)
+    defer { sqlite3_finalize(statement) }
+    try check(db, sqlite3_bind_text(statement, 1, name, -1, SQLITE_TRANSIENT))
+    guard try step(db, statement) else { return nil }
+    return User(id: sqlite3_column_int64(statement, 0), name: String(cString: sqlite3_column_text(statement, 1)), email: sqlite3_column_type(statement, 2) == SQLITE_NULL ? nil : String(cString: sqlite3_column_text(statement, 2)))
+}
+"#;
+        let output = generate(input);
+        assert!(output.starts_with("import SQLite3\n"));
+        assert!(!output.contains("class Rows"));
+        assert!(!output.contains("protocol SQLiteValue"));
+        assert!(output.ends_with(expected), "{}", output);
+    }
+
+    #[test]
+    fn it_returns_a_lazy_sequence_for_iterators() {
+        let input = "
+        -- @query get_range(low: i32, high: Option<f32>) -> Iterator<(i64, Color)>
+        select id as \"id: i64\", color as \"color: Color\" from t
+        where id >= :low and id < :high and id <> :low;
+        ";
+        let output = generate(input);
+        assert!(output.contains("public final class Rows<Element>: Sequence, IteratorProtocol {"));
+        assert!(output.contains("public protocol SQLiteValue {"));
+        assert!(output.contains(
+            "public func getRange(_ db: OpaquePointer, low: Int32, high: Float?) throws -> Rows<(Int64, Color)> {"
+        ));
+        assert!(output.contains(
+            "    let rows = Rows(db, statement) { statement in\n        (sqlite3_column_int64(statement, 0), Color(statement: statement, index: 1))\n    }\n"
+        ));
+        assert!(output.contains("    try check(db, sqlite3_bind_int(statement, 1, low))\n"));
+        assert!(output.contains(
+            "    try check(db, high == nil ? sqlite3_bind_null(statement, 2) : sqlite3_bind_double(statement, 2, Double(high!)))\n"
+        ));
+        assert!(!output.contains("bind_int(statement, 3"));
+        assert!(output.contains("    return rows\n"));
+    }
+
+    #[test]
+    fn structs_with_custom_fields_are_not_decodable() {
+        let input = "
+        -- @query get_shape() -> Shape
+        select id as \"id: i64\", color as \"color: Color\" from t;
+        -- @query get_point() -> Point
+        select x as \"x: f64\", y as \"y: f64\" from t;
+        ";
+        let output = generate(input);
+        assert!(output.contains("public protocol SQLiteValue {"));
+        assert!(output.contains(
+            "public struct Shape {\n    public let id: Int64\n    public let color: Color\n}\n"
+        ));
+        assert!(output.contains("public struct Point: Decodable {\n"));
+    }
+
+    #[test]
+    fn it_throws_when_a_required_row_is_missing() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input);
+        assert!(output.contains("public func setupSchema(_ db: OpaquePointer) throws {\n"));
+        assert!(output.contains("    while try step(db, statement) {}\n"));
+        assert!(output.contains("        throw SQLiteError(code: SQLITE_DONE, message:"));
+        assert!(output.contains("    return sqlite3_column_int64(statement, 0)\n"));
+    }
+}
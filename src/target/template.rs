@@ -0,0 +1,298 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::ast::{Document, Fragment, Query, Section, Type, TypedIdent};
+use crate::error::{Error, PResult};
+use crate::target::{collect_structs, query_text, Backend, Options, Placeholder};
+use crate::template::Value;
+use crate::Span;
+
+/// A backend that renders a user-defined template, see [`crate::template`].
+///
+/// The template is rendered once per input file. At the top level it can refer
+/// to `dialect`, `queries` and `structs`. Every query has:
+///
+///  * `name`, and `docs`, a list of doc comment lines.
+///  * `parameters` and `columns`, lists with a `name` and `type`.
+///  * `result_type`, a type.
+///  * `sql`, the query with named parameters, `sql_numbered` with `?1`-style
+///    parameters, `sql_dollar` with `$1`-style parameters, and `sql_positional`
+///    with bare `?` parameters.
+///  * `bind_params`, the parameters in the order that `sql_numbered` and
+///    `sql_dollar` number them, and `positional_params`, the parameter for
+///    every `?` in `sql_positional`. Both have a `name` and `type`.
+///
+/// A type has a `name`, its spelling in the annotation, and a `kind`, one of
+/// `unit`, `simple`, `iterator`, `option`, `tuple` and `struct`, and a flag for
+/// every kind: `is_unit`, `is_simple`, etc. Iterators and options have an
+/// `inner` type, tuples have `elements`, and structs have `fields`, a list
+/// with a `name` and `type`. Structs in `structs` have a `name` and `fields`.
+///
+/// Besides the built-in filters, the filter `map_type` maps a simple type
+/// through the `--map-type` overrides.
+pub struct Template {
+    path: PathBuf,
+    template: crate::template::Template,
+}
+
+impl Template {
+    pub fn parse(path: PathBuf, source: String) -> PResult<Self> {
+        let template = crate::template::Template::parse(source)?;
+        Ok(Template { path, template })
+    }
+}
+
+impl Backend for Template {
+    fn name(&self) -> &str {
+        "template"
+    }
+
+    fn description(&self) -> &str {
+        "Render a user-defined template."
+    }
+
+    fn supports_type_overrides(&self) -> bool {
+        true
+    }
+
+    fn generate(
+        &self,
+        input: &str,
+        doc: &Document<Span>,
+        options: &Options,
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        let root = document_value(&doc.resolve(input), options);
+        let filter = |name: &str, text: &str| match name {
+            "map_type" => Some(options.type_override(text).unwrap_or(text).to_string()),
+            _ => None,
+        };
+        match self.template.render(&root, &filter) {
+            Ok(output) => out.write_all(output.as_bytes()),
+            Err(err) => {
+                // The error refers to the template, not to the input.
+                let err: Box<dyn Error> = err.into();
+                err.print(&self.path, self.template.source().as_bytes());
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Failed to render template '{}'.", self.path.display()),
+                ))
+            }
+        }
+    }
+}
+
+fn str(s: &str) -> Value {
+    Value::Str(s.to_string())
+}
+
+/// Format a type as it would be written in an annotation.
+fn format_type(type_: &Type<&str>) -> String {
+    match type_ {
+        Type::Unit => "()".to_string(),
+        Type::Simple(t) => t.to_string(),
+        Type::Iterator(t) => format!("Iterator<{}>", format_type(t)),
+        Type::Option(t) => format!("Option<{}>", format_type(t)),
+        Type::Tuple(ts) => {
+            let elements: Vec<String> = ts.iter().map(format_type).collect();
+            format!("({})", elements.join(", "))
+        }
+        Type::Struct(name, _fields) => name.to_string(),
+    }
+}
+
+fn type_value(type_: &Type<&str>) -> Value {
+    let kind = match type_ {
+        Type::Unit => "unit",
+        Type::Simple(..) => "simple",
+        Type::Iterator(..) => "iterator",
+        Type::Option(..) => "option",
+        Type::Tuple(..) => "tuple",
+        Type::Struct(..) => "struct",
+    };
+    let mut fields = vec![
+        ("name", Value::Str(format_type(type_))),
+        ("kind", str(kind)),
+    ];
+    for (flag, flag_kind) in &[
+        ("is_unit", "unit"),
+        ("is_simple", "simple"),
+        ("is_iterator", "iterator"),
+        ("is_option", "option"),
+        ("is_tuple", "tuple"),
+        ("is_struct", "struct"),
+    ] {
+        fields.push((flag, Value::Bool(kind == *flag_kind)));
+    }
+    match type_ {
+        Type::Iterator(inner) | Type::Option(inner) => fields.push(("inner", type_value(inner))),
+        Type::Tuple(ts) => {
+            fields.push(("elements", Value::List(ts.iter().map(type_value).collect())))
+        }
+        Type::Struct(_name, struct_fields) => {
+            fields.push(("fields", typed_idents_value(struct_fields)))
+        }
+        Type::Unit | Type::Simple(..) => {}
+    }
+    Value::Map(fields)
+}
+
+fn typed_idents_value(typed_idents: &[TypedIdent<&str>]) -> Value {
+    Value::List(
+        typed_idents
+            .iter()
+            .map(|ti| {
+                Value::Map(vec![
+                    ("name", str(ti.ident)),
+                    ("type", type_value(&ti.type_)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Return the parameters to bind, looked up by name in the declared parameters.
+fn params_value(query: &Query<&str>, names: &[&str]) -> Value {
+    let params = &query.annotation.parameters;
+    Value::List(
+        names
+            .iter()
+            .map(|name| {
+                let type_ = match params.iter().find(|p| p.ident == *name) {
+                    Some(param) => type_value(&param.type_),
+                    None => type_value(&Type::Unit),
+                };
+                Value::Map(vec![("name", str(name)), ("type", type_)])
+            })
+            .collect(),
+    )
+}
+
+fn query_value(query: &Query<&str>) -> Value {
+    let columns: Vec<TypedIdent<&str>> = query
+        .fragments
+        .iter()
+        .filter_map(|fragment| match fragment {
+            Fragment::TypedIdent(_, typed_ident) => Some(typed_ident.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let (sql, _) = query_text(query, Placeholder::Named);
+    let (sql_numbered, bind_params) = query_text(query, Placeholder::QuestionNumbered);
+    let (sql_dollar, _) = query_text(query, Placeholder::Dollar);
+    let (sql_positional, positional_params) = query_text(query, Placeholder::Question);
+
+    Value::Map(vec![
+        ("name", str(query.annotation.name)),
+        (
+            "docs",
+            Value::List(query.docs.iter().map(|d| str(d)).collect()),
+        ),
+        (
+            "parameters",
+            typed_idents_value(&query.annotation.parameters),
+        ),
+        ("columns", typed_idents_value(&columns)),
+        ("result_type", type_value(&query.annotation.result_type)),
+        ("sql", Value::Str(sql)),
+        ("sql_numbered", Value::Str(sql_numbered)),
+        ("sql_dollar", Value::Str(sql_dollar)),
+        ("sql_positional", Value::Str(sql_positional)),
+        ("bind_params", params_value(query, &bind_params)),
+        ("positional_params", params_value(query, &positional_params)),
+    ])
+}
+
+fn document_value(doc: &Document<&str>, options: &Options) -> Value {
+    let queries = doc
+        .sections
+        .iter()
+        .filter_map(|section| match section {
+            Section::Query(query) => Some(query_value(query)),
+            Section::Verbatim(..) => None,
+        })
+        .collect();
+    let structs = collect_structs(doc)
+        .into_iter()
+        .map(|(name, fields)| {
+            Value::Map(vec![
+                ("name", str(name)),
+                ("fields", typed_idents_value(fields)),
+            ])
+        })
+        .collect();
+    Value::Map(vec![
+        ("dialect", str(options.dialect.name())),
+        ("queries", Value::List(queries)),
+        ("structs", Value::List(structs)),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::Template;
+    use crate::analysis::structs::resolve_structs;
+    use crate::dialect::Dialect;
+    use crate::lexer::sql::Lexer;
+    use crate::parser::document::Parser;
+    use crate::target::{Backend, Options};
+
+    #[test]
+    fn it_renders_queries_and_structs() {
+        let input = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String" from users where name = :name;
+"#;
+        let template = "\
+{% for struct in structs %}
+class {{ struct.name }}:
+{% for field in struct.fields %}
+    {{ field.name }}: {{ field.type.name | map_type }}
+{% endfor %}
+{% endfor %}
+{% for query in queries %}
+
+{% for line in query.docs %}
+#{{ line }}
+{% endfor %}
+def {{ query.name | camel }}({% for p in query.parameters %}{{ p.name }}{% endfor %}):
+{% if query.result_type.is_option %}
+    # Returns {{ query.result_type.inner.name }} or nothing.
+{% endif %}
+    sql = '{{ query.sql_dollar }}'
+    params = [{% for p in query.bind_params %}{{ p.name }}: {{ p.type.name }}{% endfor %}]
+{% endfor %}
+";
+        let tokens = Lexer::new(input).run().unwrap();
+        let mut parser = Parser::new(input, &tokens);
+        let mut doc = parser.parse_document().unwrap();
+        resolve_structs(input, &mut doc);
+        let options = Options {
+            dialect: Dialect::Postgres,
+            type_overrides: vec![("i64".to_string(), "int".to_string())],
+        };
+        let backend = Template::parse("t.txt".into(), template.to_string()).unwrap();
+        let mut out = Vec::new();
+        backend.generate(input, &doc, &options, &mut out).unwrap();
+        let expected = "\
+class User:
+    id: int
+    name: String
+
+# Look up a user by username.
+def getUserByName(name):
+    # Returns User or nothing.
+    sql = 'select id as id, name as name from users where name = $1;'
+    params = [name: &str]
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
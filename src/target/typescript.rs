@@ -0,0 +1,484 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::io;
+
+use crate::ast::{Document, Query, Section, Type, TypedIdent};
+use crate::dialect::Dialect;
+use crate::target::{
+    collect_structs, query_text, to_camel_case, unsupported_dialect, Options, Placeholder,
+};
+use crate::Span;
+
+/// TypeScript types for simple types, for values that `better-sqlite3` returns.
+///
+/// Integers are numbers unless the statement uses safe integers, and SQLite
+/// has no booleans, it stores them as integers.
+const SQLITE_TYPES: &[(&str, &str)] = &[
+    ("i8", "number"),
+    ("i16", "number"),
+    ("i32", "number"),
+    ("i64", "number"),
+    ("u8", "number"),
+    ("u16", "number"),
+    ("u32", "number"),
+    ("u64", "number"),
+    ("f32", "number"),
+    ("f64", "number"),
+    ("bool", "number"),
+    ("&str", "string"),
+    ("String", "string"),
+    ("&[u8]", "Buffer"),
+];
+
+/// TypeScript types for simple types, for values that `pg` returns.
+///
+/// By default, `pg` returns 64-bit integers as strings, because they do not
+/// fit in a number without loss of precision.
+const POSTGRES_TYPES: &[(&str, &str)] = &[
+    ("i8", "number"),
+    ("i16", "number"),
+    ("i32", "number"),
+    ("i64", "string"),
+    ("u8", "number"),
+    ("u16", "number"),
+    ("u32", "number"),
+    ("u64", "string"),
+    ("f32", "number"),
+    ("f64", "number"),
+    ("bool", "boolean"),
+    ("&str", "string"),
+    ("String", "string"),
+    ("&[u8]", "Buffer"),
+];
+
+/// Generate TypeScript code.
+///
+/// For SQLite, the code uses `better-sqlite3`, for Postgres it uses `pg`.
+pub fn process_file(
+    input: &str,
+    parsed: &Document<Span>,
+    options: &Options,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let doc = parsed.resolve(input);
+    let types = Types { options };
+
+    match options.dialect {
+        Dialect::Sqlite => writeln!(out, "import Database from \"better-sqlite3\";")?,
+        Dialect::Postgres => writeln!(out, "import type {{ ClientBase }} from \"pg\";")?,
+        Dialect::Mysql => return Err(unsupported_dialect("typescript", options.dialect)),
+    }
+
+    for (name, fields) in collect_structs(&doc) {
+        write_interface(&types, name, fields, out)?;
+    }
+
+    for section in &doc.sections {
+        if let Section::Query(query) = section {
+            writeln!(out)?;
+            write_doc_comment(&query.docs, out)?;
+            match options.dialect {
+                Dialect::Sqlite => write_better_sqlite3_function(&types, query, out)?,
+                Dialect::Postgres => write_pg_function(&types, query, out)?,
+                Dialect::Mysql => unreachable!("We returned an error for MySQL above."),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The mapping from annotation types to TypeScript types.
+struct Types<'a> {
+    options: &'a Options,
+}
+
+impl<'a> Types<'a> {
+    /// Format a type as a TypeScript type.
+    ///
+    /// Iterators have no direct counterpart, the functions format result
+    /// iterators in their own way, this function formats them as arrays.
+    fn format(&self, type_: &Type<&str>) -> String {
+        match type_ {
+            Type::Unit => "void".to_string(),
+            Type::Simple(t) => self.format_simple(t),
+            Type::Iterator(t) => format!("{}[]", self.format_row(t)),
+            Type::Option(t) => format!("{} | null", self.format(t)),
+            Type::Tuple(ts) => {
+                let elements: Vec<String> = ts.iter().map(|t| self.format(t)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Type::Struct(name, _fields) => name.to_string(),
+        }
+    }
+
+    fn format_simple(&self, type_: &str) -> String {
+        if let Some(result) = self.options.type_override(type_) {
+            return result.to_string();
+        }
+        let table = match self.options.dialect {
+            Dialect::Sqlite => SQLITE_TYPES,
+            Dialect::Postgres => POSTGRES_TYPES,
+            Dialect::Mysql => unreachable!("MySQL is not supported by this target."),
+        };
+        match table.iter().find(|(from, _)| *from == type_) {
+            Some((_, to)) => to.to_string(),
+            None => type_.to_string(),
+        }
+    }
+
+    /// Format the type of a row, parenthesized if it needs to be in an array.
+    fn format_row(&self, type_: &Type<&str>) -> String {
+        match type_ {
+            Type::Option(..) => format!("({})", self.format(type_)),
+            _ => self.format(type_),
+        }
+    }
+}
+
+fn write_interface(
+    types: &Types,
+    name: &str,
+    fields: &[TypedIdent<&str>],
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    writeln!(out)?;
+    writeln!(out, "export interface {} {{", name)?;
+    for field in fields {
+        writeln!(out, "  {}: {};", field.ident, types.format(&field.type_))?;
+    }
+    writeln!(out, "}}")
+}
+
+fn write_doc_comment(docs: &[&str], out: &mut dyn io::Write) -> io::Result<()> {
+    if docs.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "/**")?;
+    for line in docs {
+        // Do not let the documentation end the comment early.
+        let line = line.replace("*/", "*\\/");
+        if line.trim().is_empty() {
+            writeln!(out, " *")?;
+        } else {
+            writeln!(out, " *{}", line)?;
+        }
+    }
+    writeln!(out, " */")
+}
+
+/// Format the query as a template literal.
+fn format_template_literal(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('`', "\\`")
+        .replace("${", "\\${");
+    format!("`{}`", escaped)
+}
+
+/// Write `export function name(connection, params): ` up to the return type.
+fn write_signature(
+    types: &Types,
+    query: &Query<&str>,
+    qualifiers: &str,
+    connection: &str,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let annotation = &query.annotation;
+    write!(
+        out,
+        "export {} {}({}",
+        qualifiers,
+        to_camel_case(annotation.name, false),
+        connection
+    )?;
+    for param in &annotation.parameters {
+        write!(out, ", {}: {}", param.ident, types.format(&param.type_))?;
+    }
+    write!(out, "): ")
+}
+
+fn write_better_sqlite3_function(
+    types: &Types,
+    query: &Query<&str>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let result_type = &query.annotation.result_type;
+    let connection = "db: Database.Database";
+    write_signature(types, query, "function", connection, out)?;
+    match result_type {
+        Type::Iterator(inner) => {
+            writeln!(out, "IterableIterator<{}> {{", types.format(inner))?;
+        }
+        other => writeln!(out, "{} {{", types.format(other))?,
+    }
+
+    let (text, params) = query_text(query, Placeholder::Named);
+    let params: Vec<String> = params.iter().map(|p| format!("{}: {}", p, p)).collect();
+    let params = match params.len() {
+        0 => "{}".to_string(),
+        _ => format!("{{ {} }}", params.join(", ")),
+    };
+
+    let row_type = match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => &**inner,
+        other => other,
+    };
+    // Pluck the first column for scalars, and get arrays for tuples, objects
+    // keyed by column name are what we want for structs.
+    let mode = match row_type {
+        Type::Simple(..) | Type::Option(..) => "\n    .pluck()",
+        Type::Tuple(..) => "\n    .raw()",
+        _ => "",
+    };
+
+    writeln!(out, "  const statement = db")?;
+    writeln!(
+        out,
+        "    .prepare({}){};",
+        format_template_literal(&text),
+        mode
+    )?;
+
+    match result_type {
+        Type::Unit => {
+            writeln!(out, "  statement.run({});", params)?;
+        }
+        Type::Iterator(inner) => {
+            writeln!(
+                out,
+                "  return statement.iterate({}) as IterableIterator<{}>;",
+                params,
+                types.format(inner)
+            )?;
+        }
+        Type::Option(inner) => {
+            writeln!(out, "  const row = statement.get({});", params)?;
+            writeln!(
+                out,
+                "  return row === undefined ? null : (row as {});",
+                types.format(inner)
+            )?;
+        }
+        other => {
+            writeln!(out, "  const row = statement.get({});", params)?;
+            writeln!(out, "  if (row === undefined) {{")?;
+            writeln!(
+                out,
+                "    throw new Error(\"Expected a row, but the query returned none.\");"
+            )?;
+            writeln!(out, "  }}")?;
+            writeln!(out, "  return row as {};", types.format(other))?;
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+fn write_pg_function(
+    types: &Types,
+    query: &Query<&str>,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let result_type = &query.annotation.result_type;
+    let connection = "client: ClientBase";
+    write_signature(types, query, "async function", connection, out)?;
+    writeln!(out, "Promise<{}> {{", types.format(result_type))?;
+
+    let (text, params) = query_text(query, Placeholder::Dollar);
+
+    let row_type = match result_type {
+        Type::Option(inner) | Type::Iterator(inner) => &**inner,
+        other => other,
+    };
+    // Scalars and tuples are read by index, structs by column name.
+    let (query_type, array_mode, read_row) = match row_type {
+        Type::Unit => (String::new(), false, ""),
+        Type::Struct(..) => (format!("<{}>", types.format(row_type)), false, ""),
+        Type::Tuple(..) => (format!("<{}>", types.format(row_type)), true, ""),
+        _ => (format!("<[{}]>", types.format_row(row_type)), true, "[0]"),
+    };
+
+    match result_type {
+        Type::Unit => write!(out, "  await client.query({{")?,
+        _ => write!(out, "  const result = await client.query{}({{", query_type)?,
+    }
+    writeln!(out)?;
+    writeln!(out, "    text: {},", format_template_literal(&text))?;
+    writeln!(out, "    values: [{}],", params.join(", "))?;
+    if array_mode {
+        writeln!(out, "    rowMode: \"array\",")?;
+    }
+    writeln!(out, "  }});")?;
+
+    match result_type {
+        Type::Unit => {}
+        Type::Iterator(..) if read_row.is_empty() => {
+            writeln!(out, "  return result.rows;")?;
+        }
+        Type::Iterator(..) => {
+            writeln!(out, "  return result.rows.map((row) => row{});", read_row)?;
+        }
+        Type::Option(..) => {
+            writeln!(
+                out,
+                "  return result.rows.length === 0 ? null : result.rows[0]{};",
+                read_row
+            )?;
+        }
+        _ => {
+            writeln!(out, "  if (result.rows.length === 0) {{")?;
+            writeln!(
+                out,
+                "    throw new Error(\"Expected a row, but the query returned none.\");"
+            )?;
+            writeln!(out, "  }}")?;
+            writeln!(out, "  return result.rows[0]{};", read_row)?;
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_file;
+    use crate::dialect::Dialect;
+    use crate::target::generate_for_test;
+    use crate::target::Options;
+
+    fn generate(input: &str, options: &Options) -> String {
+        generate_for_test(input, |input, doc, out| {
+            process_file(input, doc, options, out)
+        })
+    }
+
+    fn options(dialect: Dialect) -> Options {
+        Options {
+            dialect,
+            type_overrides: Vec::new(),
+        }
+    }
+
+    const README_EXAMPLE: &str = r#"-- Look up a user by username.
+-- @query get_user_by_name(name: &str) -> Option<User>
+select id as "id: i64", name as "name: String", email as "email: Option<String>"
+from users
+where name = :name;
+"#;
+
+    #[test]
+    fn it_generates_the_readme_example_for_better_sqlite3() {
+        let expected = r#"import Database from "better-sqlite3";
+
+export interface User {
+  id: number;
+  name: string;
+  email: string | null;
+}
+
+/**
+ * Look up a user by username.
+ */
+export function getUserByName(db: Database.Database, name: string): User | null {
+  const statement = db
+    .prepare(`select id as id, name as name, email as email
+from users
+where name = :name;`);
+  const row = statement.get({ name: name });
+  return row === undefined ? null : (row as User);
+}
+"#;
+        assert_eq!(
+            generate(README_EXAMPLE, &options(Dialect::Sqlite)),
+            expected
+        );
+    }
+
+    #[test]
+    fn it_generates_the_readme_example_for_pg() {
+        let expected = r#"import type { ClientBase } from "pg";
+
+export interface User {
+  id: string;
+  name: string;
+  email: string | null;
+}
+
+/**
+ * Look up a user by username.
+ */
+export async function getUserByName(client: ClientBase, name: string): Promise<User | null> {
+  const result = await client.query<User>({
+    text: `select id as id, name as name, email as email
+from users
+where name = $1;`,
+    values: [name],
+  });
+  return result.rows.length === 0 ? null : result.rows[0];
+}
+"#;
+        assert_eq!(
+            generate(README_EXAMPLE, &options(Dialect::Postgres)),
+            expected
+        );
+    }
+
+    #[test]
+    fn it_applies_type_overrides() {
+        let mut options = options(Dialect::Sqlite);
+        options.type_overrides = vec![
+            ("i64".to_string(), "bigint".to_string()),
+            ("Email".to_string(), "`${string}@${string}`".to_string()),
+        ];
+        let input = "
+        -- @query get_range(low: i64, high: i64) -> Iterator<(i64, Email)>
+        select id as \"id: i64\", email as \"email: Email\" from t
+        where id >= :low and id < :high;
+        ";
+        let output = generate(input, &options);
+        assert!(output.contains(
+            "(db: Database.Database, low: bigint, high: bigint): IterableIterator<[bigint, `${string}@${string}`]> {"
+        ));
+        assert!(output.contains("    .raw();\n"));
+        assert!(output.contains("  return statement.iterate({ low: low, high: high }) as"));
+    }
+
+    #[test]
+    fn it_reads_scalars_by_index_for_pg() {
+        let input = std::fs::read_to_string("examples/users.sql").unwrap();
+        let output = generate(&input, &options(Dialect::Postgres));
+        assert!(output.contains("  const result = await client.query<[string]>({\n"));
+        assert!(output.contains("    rowMode: \"array\",\n"));
+        assert!(output.contains("  return result.rows[0][0];\n"));
+        assert!(output.contains("  await client.query({\n"));
+        assert!(output.contains("): Promise<User[]> {\n"));
+        assert!(output.contains("  return result.rows;\n"));
+    }
+
+    #[test]
+    fn it_parenthesizes_nullable_array_elements() {
+        let input = "
+        -- @query get_emails() -> Iterator<Option<String>>
+        select email as \"email: Option<String>\" from users;
+        ";
+        let output = generate(input, &options(Dialect::Postgres));
+        assert!(output.contains("(client: ClientBase): Promise<(string | null)[]> {\n"));
+    }
+
+    #[test]
+    fn it_escapes_template_literals() {
+        let input = "
+        -- @query get_template() -> String
+        select '`${x}\\b' as \"t: String\";
+        ";
+        let output = generate(input, &options(Dialect::Sqlite));
+        assert!(output.contains("select '\\`\\${x}\\\\b' as t;`"));
+        assert!(output.contains("    .pluck();\n"));
+    }
+}
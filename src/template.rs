@@ -0,0 +1,646 @@
+// Querybinder -- Generate boilerplate from SQL for statically typed languages
+// Copyright 2022 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A small template language, for user-defined code generation.
+//!
+//! The syntax is a subset of Jinja:
+//!
+//!  * `{{ path | filter }}` prints a value, optionally through filters.
+//!    A path is a dotted name, e.g. `query.result_type.name`.
+//!  * `{% for x in path %} ... {% endfor %}` repeats for every list element.
+//!    Inside the loop, `loop.index`, `loop.first` and `loop.last` are defined.
+//!  * `{% if path %} ... {% else %} ... {% endif %}` tests a value.
+//!    `if not path` negates the test. Empty strings and lists, zero, and false
+//!    are false, everything else is true.
+//!  * `{# ... #}` is a comment.
+//!
+//! A `-` on the inside of a delimiter, as in `{%-` or `-}}`, removes the
+//! whitespace before or after the tag. A line that contains nothing but a
+//! `{% %}` or `{# #}` tag is removed entirely, including its newline.
+
+use crate::error::{PResult, ParseError};
+use crate::Span;
+
+/// A value that templates can refer to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Int(usize),
+    Str(String),
+    List(Vec<Value>),
+    Map(Vec<(&'static str, Value)>),
+}
+
+impl Value {
+    /// Return whether `if` considers the value true.
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Str(s) => !s.is_empty(),
+            Value::List(xs) => !xs.is_empty(),
+            Value::Map(..) => true,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(fields) => fields.iter().find(|(k, _)| *k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// A dotted name, e.g. `query.name`.
+#[derive(Debug)]
+struct Path {
+    span: Span,
+    negated: bool,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(Span),
+    Print {
+        path: Path,
+        filters: Vec<Span>,
+    },
+    For {
+        var: Span,
+        list: Path,
+        body: Vec<Node>,
+    },
+    If {
+        cond: Path,
+        then: Vec<Node>,
+        else_: Vec<Node>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TagKind {
+    Print,
+    Block,
+    Comment,
+}
+
+#[derive(Debug)]
+enum Token {
+    Text(Span),
+    /// A tag, with the span of its contents, excluding delimiters.
+    Tag(TagKind, Span, Span),
+}
+
+/// Split the template into text and tags, and apply whitespace control.
+fn tokenize(input: &str) -> PResult<Vec<Token>> {
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    // Where the next text token starts, whitespace control can move it.
+    let mut text_start = 0;
+
+    while let Some(offset) = input[pos..].find('{') {
+        let start = pos + offset;
+        let (kind, close) = match bytes.get(start + 1) {
+            Some(b'{') => (TagKind::Print, "}}"),
+            Some(b'%') => (TagKind::Block, "%}"),
+            Some(b'#') => (TagKind::Comment, "#}"),
+            _ => {
+                pos = start + 1;
+                continue;
+            }
+        };
+        let open_span = Span {
+            start,
+            end: start + 2,
+        };
+        let end = match input[start + 2..].find(close) {
+            Some(n) => start + 2 + n + 2,
+            None => {
+                return Err(ParseError {
+                    span: open_span,
+                    message: "Expected the tag to be closed, but it is not.",
+                    note: None,
+                })
+            }
+        };
+        let tag_span = Span { start, end };
+
+        let mut inner = Span {
+            start: start + 2,
+            end: end - 2,
+        };
+        let trim_before = kind != TagKind::Comment && bytes[inner.start] == b'-';
+        let trim_after =
+            kind != TagKind::Comment && inner.len() > 0 && bytes[inner.end - 1] == b'-';
+        if trim_before {
+            inner.start += 1;
+        }
+        if trim_after && inner.end > inner.start {
+            inner.end -= 1;
+        }
+
+        // A block or comment tag on a line of its own removes the line.
+        let line_start = input[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = input[end..].find('\n').map(|i| end + i + 1);
+        let own_line = kind != TagKind::Print
+            && input[line_start..start].trim().is_empty()
+            && input[end..line_end.unwrap_or(input.len())]
+                .trim()
+                .is_empty();
+
+        let mut text_end = start;
+        if trim_before {
+            text_end = text_start + input[text_start..start].trim_end().len();
+        } else if own_line && line_start >= text_start {
+            text_end = line_start;
+        }
+        if text_end > text_start {
+            tokens.push(Token::Text(Span {
+                start: text_start,
+                end: text_end,
+            }));
+        }
+        tokens.push(Token::Tag(kind, tag_span, inner));
+
+        pos = end;
+        text_start = end;
+        if trim_after {
+            text_start = input.len() - input[end..].trim_start().len();
+        } else if own_line {
+            text_start = line_end.unwrap_or(input.len());
+        }
+        pos = pos.max(text_start);
+    }
+
+    if input.len() > text_start {
+        tokens.push(Token::Text(Span {
+            start: text_start,
+            end: input.len(),
+        }));
+    }
+
+    Ok(tokens)
+}
+
+/// Split the contents of a tag into words, with `|` as a separate word.
+fn words(input: &str, span: Span) -> Vec<Span> {
+    let mut result = Vec::new();
+    let mut word_start = None;
+    for (i, ch) in span.resolve(input).char_indices() {
+        let at = span.start + i;
+        if ch.is_whitespace() || ch == '|' {
+            if let Some(start) = word_start.take() {
+                result.push(Span { start, end: at });
+            }
+            if ch == '|' {
+                result.push(Span {
+                    start: at,
+                    end: at + 1,
+                });
+            }
+        } else if word_start.is_none() {
+            word_start = Some(at);
+        }
+    }
+    if let Some(start) = word_start {
+        result.push(Span {
+            start,
+            end: span.end,
+        });
+    }
+    result
+}
+
+fn is_path(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').all(|part| {
+            !part.is_empty()
+                && part.bytes().all(crate::is_ascii_identifier)
+                && !part.as_bytes()[0].is_ascii_digit()
+        })
+}
+
+/// A tag that ends a block, and the words inside it.
+type EndTag = (Span, Vec<Span>);
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    cursor: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_path(&self, tag: Span, words: &[Span]) -> PResult<Path> {
+        let (negated, words) = match words.split_first() {
+            Some((first, rest)) if first.resolve(self.input) == "not" => (true, rest),
+            _ => (false, words),
+        };
+        match words {
+            [name] if is_path(name.resolve(self.input)) => Ok(Path {
+                span: *name,
+                negated,
+            }),
+            [name] => Err(ParseError {
+                span: *name,
+                message: "Expected a name like 'query.name'.",
+                note: None,
+            }),
+            _ => Err(ParseError {
+                span: tag,
+                message: "Expected a single name.",
+                note: None,
+            }),
+        }
+    }
+
+    fn parse_print(&self, tag: Span, inner: Span) -> PResult<Node> {
+        let words = words(self.input, inner);
+        let mut parts = words.split(|w| w.resolve(self.input) == "|");
+        let path = self.parse_path(tag, parts.next().unwrap_or(&[]))?;
+        if path.negated {
+            return Err(ParseError {
+                span: tag,
+                message: "Expected a name, 'not' is only allowed in 'if'.",
+                note: None,
+            });
+        }
+        let mut filters = Vec::new();
+        for part in parts {
+            match part {
+                [filter] if is_path(filter.resolve(self.input)) => filters.push(*filter),
+                _ => {
+                    return Err(ParseError {
+                        span: tag,
+                        message: "Expected a filter name after '|'.",
+                        note: None,
+                    })
+                }
+            }
+        }
+        Ok(Node::Print { path, filters })
+    }
+
+    /// Parse nodes until a block tag that `is_end` accepts, or the end of input.
+    ///
+    /// Returns the nodes, and the words of the end tag if there was one.
+    fn parse_nodes(
+        &mut self,
+        is_end: &dyn Fn(&str) -> bool,
+    ) -> PResult<(Vec<Node>, Option<EndTag>)> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.tokens.get(self.cursor) {
+            self.cursor += 1;
+            let (tag, inner) = match *token {
+                Token::Text(span) => {
+                    nodes.push(Node::Text(span));
+                    continue;
+                }
+                Token::Tag(TagKind::Comment, ..) => continue,
+                Token::Tag(TagKind::Print, tag, inner) => {
+                    nodes.push(self.parse_print(tag, inner)?);
+                    continue;
+                }
+                Token::Tag(TagKind::Block, tag, inner) => (tag, inner),
+            };
+            let words = words(self.input, inner);
+            let keyword = match words.first() {
+                Some(word) => word.resolve(self.input),
+                None => {
+                    return Err(ParseError {
+                        span: tag,
+                        message: "Expected 'for', 'if', 'else', 'endfor', or 'endif'.",
+                        note: None,
+                    })
+                }
+            };
+            if is_end(keyword) {
+                return Ok((nodes, Some((tag, words))));
+            }
+            match keyword {
+                "for" => nodes.push(self.parse_for(tag, &words)?),
+                "if" => nodes.push(self.parse_if(tag, &words)?),
+                "else" | "endfor" | "endif" => {
+                    return Err(ParseError {
+                        span: tag,
+                        message: "Unexpected tag, there is no block to close here.",
+                        note: None,
+                    })
+                }
+                _ => {
+                    return Err(ParseError {
+                        span: words[0],
+                        message: "Expected 'for', 'if', 'else', 'endfor', or 'endif'.",
+                        note: None,
+                    })
+                }
+            }
+        }
+        Ok((nodes, None))
+    }
+
+    fn parse_for(&mut self, tag: Span, words: &[Span]) -> PResult<Node> {
+        let (var, list) = match words {
+            [_for, var, in_, rest @ ..] if in_.resolve(self.input) == "in" => {
+                (*var, self.parse_path(tag, rest)?)
+            }
+            _ => {
+                return Err(ParseError {
+                    span: tag,
+                    message: "Expected a loop of the form 'for x in list'.",
+                    note: None,
+                })
+            }
+        };
+        let var_name = var.resolve(self.input);
+        if list.negated || var_name.contains('.') || !is_path(var_name) {
+            return Err(ParseError {
+                span: tag,
+                message: "Expected a loop of the form 'for x in list'.",
+                note: None,
+            });
+        }
+        let (body, end) = self.parse_nodes(&|kw| kw == "endfor")?;
+        self.expect_end(tag, end, "Expected '{% endfor %}' to close this loop.")?;
+        Ok(Node::For { var, list, body })
+    }
+
+    fn parse_if(&mut self, tag: Span, words: &[Span]) -> PResult<Node> {
+        let cond = self.parse_path(tag, &words[1..])?;
+        let (then, end) = self.parse_nodes(&|kw| kw == "else" || kw == "endif")?;
+        let (end_tag, end_words) =
+            self.expect_end(tag, end, "Expected '{% endif %}' to close this condition.")?;
+        let else_ = if end_words[0].resolve(self.input) == "else" {
+            if end_words.len() > 1 {
+                return Err(ParseError {
+                    span: end_tag,
+                    message: "Expected '{% else %}' without a condition.",
+                    note: None,
+                });
+            }
+            let (else_, end) = self.parse_nodes(&|kw| kw == "endif")?;
+            self.expect_end(tag, end, "Expected '{% endif %}' to close this condition.")?;
+            else_
+        } else {
+            Vec::new()
+        };
+        Ok(Node::If { cond, then, else_ })
+    }
+
+    fn expect_end(
+        &self,
+        open_tag: Span,
+        end: Option<EndTag>,
+        message: &'static str,
+    ) -> PResult<(Span, Vec<Span>)> {
+        match end {
+            Some((tag, words)) if words.len() == 1 || words[0].resolve(self.input) == "else" => {
+                Ok((tag, words))
+            }
+            Some((tag, _words)) => Err(ParseError {
+                span: tag,
+                message: "Unexpected content after the keyword.",
+                note: None,
+            }),
+            None => Err(ParseError {
+                span: open_tag,
+                message,
+                note: None,
+            }),
+        }
+    }
+}
+
+/// A parsed template.
+pub struct Template {
+    source: String,
+    nodes: Vec<Node>,
+}
+
+/// Variables bound by enclosing loops, innermost last.
+type Scope<'v> = Vec<(&'v str, Value)>;
+
+impl Template {
+    pub fn parse(source: String) -> PResult<Template> {
+        let tokens = tokenize(&source)?;
+        let mut parser = Parser {
+            input: &source,
+            tokens,
+            cursor: 0,
+        };
+        let (nodes, _end) = parser.parse_nodes(&|_| false)?;
+        Ok(Template { source, nodes })
+    }
+
+    /// The template source, that spans in errors refer to.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Render the template.
+    ///
+    /// The built-in filters are `camel`, `pascal`, `upper` and `lower`. Any
+    /// other filter is looked up with `filter`, which is called with the name
+    /// of the filter and its input.
+    pub fn render(
+        &self,
+        root: &Value,
+        filter: &dyn Fn(&str, &str) -> Option<String>,
+    ) -> PResult<String> {
+        let mut out = String::new();
+        let mut scope = Vec::new();
+        self.render_nodes(&self.nodes, root, &mut scope, filter, &mut out)?;
+        Ok(out)
+    }
+
+    fn lookup<'v>(
+        &self,
+        path: &Path,
+        root: &'v Value,
+        scope: &'v [(&str, Value)],
+    ) -> PResult<&'v Value> {
+        let name = path.span.resolve(&self.source);
+        let mut parts = name.split('.');
+        let first = parts.next().expect("Split yields at least one part.");
+        let mut value = match scope.iter().rev().find(|(var, _)| *var == first) {
+            Some((_, value)) => value,
+            None => root.get(first).ok_or(ParseError {
+                span: path.span,
+                message: "Unknown variable.",
+                note: None,
+            })?,
+        };
+        for part in parts {
+            value = value.get(part).ok_or(ParseError {
+                span: path.span,
+                message: "This value has no such field.",
+                note: None,
+            })?;
+        }
+        Ok(value)
+    }
+
+    fn render_nodes<'v>(
+        &'v self,
+        nodes: &[Node],
+        root: &Value,
+        scope: &mut Scope<'v>,
+        filter: &dyn Fn(&str, &str) -> Option<String>,
+        out: &mut String,
+    ) -> PResult<()> {
+        for node in nodes {
+            match node {
+                Node::Text(span) => out.push_str(span.resolve(&self.source)),
+                Node::Print { path, filters } => {
+                    let mut text = match self.lookup(path, root, scope)? {
+                        Value::Bool(b) => b.to_string(),
+                        Value::Int(i) => i.to_string(),
+                        Value::Str(s) => s.clone(),
+                        Value::List(..) | Value::Map(..) => {
+                            return Err(ParseError {
+                                span: path.span,
+                                message:
+                                    "Expected a string or number, but this is a list or object.",
+                                note: None,
+                            })
+                        }
+                    };
+                    for filter_span in filters {
+                        let name = filter_span.resolve(&self.source);
+                        text = match name {
+                            "camel" => crate::target::to_camel_case(&text, false),
+                            "pascal" => crate::target::to_camel_case(&text, true),
+                            "upper" => text.to_uppercase(),
+                            "lower" => text.to_lowercase(),
+                            _ => filter(name, &text).ok_or(ParseError {
+                                span: *filter_span,
+                                message: "Unknown filter.",
+                                note: None,
+                            })?,
+                        };
+                    }
+                    out.push_str(&text);
+                }
+                Node::If { cond, then, else_ } => {
+                    let value = self.lookup(cond, root, scope)?;
+                    if value.is_truthy() != cond.negated {
+                        self.render_nodes(then, root, scope, filter, out)?;
+                    } else {
+                        self.render_nodes(else_, root, scope, filter, out)?;
+                    }
+                }
+                Node::For { var, list, body } => {
+                    let elements = match self.lookup(list, root, scope)? {
+                        Value::List(xs) => xs.clone(),
+                        _ => {
+                            return Err(ParseError {
+                                span: list.span,
+                                message: "Expected a list to loop over.",
+                                note: None,
+                            })
+                        }
+                    };
+                    let n = elements.len();
+                    for (i, element) in elements.into_iter().enumerate() {
+                        let loop_info = Value::Map(vec![
+                            ("index", Value::Int(i + 1)),
+                            ("index0", Value::Int(i)),
+                            ("first", Value::Bool(i == 0)),
+                            ("last", Value::Bool(i + 1 == n)),
+                        ]);
+                        scope.push(("loop", loop_info));
+                        scope.push((var.resolve(&self.source), element));
+                        let result = self.render_nodes(body, root, scope, filter, out);
+                        scope.truncate(scope.len() - 2);
+                        result?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Template, Value};
+
+    fn render(template: &str, root: &Value) -> String {
+        let template = Template::parse(template.to_string()).expect("Failed to parse template.");
+        template
+            .render(root, &|name, text| match name {
+                "quote" => Some(format!("'{}'", text)),
+                _ => None,
+            })
+            .expect("Failed to render template.")
+    }
+
+    fn example() -> Value {
+        Value::Map(vec![
+            ("title", Value::Str("get_user".to_string())),
+            (
+                "items",
+                Value::List(vec![
+                    Value::Map(vec![("name", Value::Str("a".to_string()))]),
+                    Value::Map(vec![("name", Value::Str("b".to_string()))]),
+                ]),
+            ),
+            ("empty", Value::List(Vec::new())),
+        ])
+    }
+
+    #[test]
+    fn it_prints_values_through_filters() {
+        let root = example();
+        assert_eq!(render("x {{ title }} y", &root), "x get_user y");
+        assert_eq!(render("{{title|pascal}}", &root), "GetUser");
+        assert_eq!(render("{{ title | camel | quote }}", &root), "'getUser'");
+        assert_eq!(render("{ {{ title | upper }} }", &root), "{ GET_USER }");
+    }
+
+    #[test]
+    fn it_loops_and_branches() {
+        let root = example();
+        let template =
+            "{% for item in items %}{{ item.name }}{% if not loop.last %}, {% endif %}{% endfor %}";
+        assert_eq!(render(template, &root), "a, b");
+        let template = "{% if empty %}yes{% else %}no{% endif %}";
+        assert_eq!(render(template, &root), "no");
+    }
+
+    #[test]
+    fn it_removes_lines_with_only_block_tags() {
+        let root = example();
+        let template = "begin\n  {% for item in items %}\n  {# The name. #}\n  - {{ item.name }}\n  {% endfor %}\nend\n";
+        assert_eq!(render(template, &root), "begin\n  - a\n  - b\nend\n");
+        let template = "[\n  {{- title -}}\n]";
+        assert_eq!(render(template, &root), "[get_user]");
+    }
+
+    #[test]
+    fn it_reports_errors_at_the_right_place() {
+        let root = example();
+        let parse = |t: &str| Template::parse(t.to_string());
+        let err = parse("a {% for x in items %} b").err().unwrap();
+        assert_eq!(err.message, "Expected '{% endfor %}' to close this loop.");
+        assert_eq!((err.span.start, err.span.end), (2, 22));
+        let err = parse("a {{ b").err().unwrap();
+        assert_eq!((err.span.start, err.span.end), (2, 4));
+        let err = parse("{% endif %}").err().unwrap();
+        assert_eq!(
+            err.message,
+            "Unexpected tag, there is no block to close here."
+        );
+        let template = parse("{{ items.name }}").unwrap();
+        let err = template.render(&root, &|_, _| None).err().unwrap();
+        assert_eq!(err.message, "This value has no such field.");
+        assert_eq!((err.span.start, err.span.end), (3, 13));
+        let template = parse("{{ title | nope }}").unwrap();
+        let err = template.render(&root, &|_, _| None).err().unwrap();
+        assert_eq!(err.message, "Unknown filter.");
+    }
+}